@@ -0,0 +1,418 @@
+use crate::shell::Shell;
+use crate::vars::ReadonlyError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArithError {
+    #[error("{0}: syntax error: operand expected (error token is \"{1}\")")]
+    Operand(String, String),
+    #[error("{0}: syntax error in expression (error token is \"{1}\")")]
+    Syntax(String, String),
+    #[error("{0}: division by 0")]
+    DivideByZero(String),
+    #[error("{0}: value too great for base")]
+    Base(String),
+    #[error("{0}: expression recursion level exceeded")]
+    Recursion(String),
+    #[error(transparent)]
+    Readonly(#[from] ReadonlyError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Num(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+const OPS: &[&str] = &[
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "!", "~",
+    "?", ":", "=", "(", ")", ",",
+];
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(i64),
+    Var(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    // `name op= value`, the operator is `=` for plain assignment
+    Assign(String, &'static str, Box<Expr>),
+    // `++name` and `--name`
+    PreStep(String, i64),
+    // `name++` and `name--`
+    PostStep(String, i64),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+const MAX_DEPTH: usize = 64;
+
+pub fn eval(shell: &mut Shell, expr: &str) -> Result<i64, ArithError> {
+    eval_depth(shell, expr, 0)
+}
+
+fn eval_depth(shell: &mut Shell, expr: &str, depth: usize) -> Result<i64, ArithError> {
+    if depth > MAX_DEPTH {
+        return Err(ArithError::Recursion(expr.to_owned()));
+    }
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser {
+        expr,
+        tokens: &tokens,
+        pos: 0,
+    };
+    let ast = parser.comma()?;
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        return Err(ArithError::Syntax(expr.to_owned(), token_text(token)));
+    }
+    Evaluator { shell, expr, depth }.eval(&ast)
+}
+
+fn token_text(token: &Token) -> String {
+    match token {
+        Token::Num(v) => v.to_string(),
+        Token::Name(v) => v.clone(),
+        Token::Op(v) => v.to_string(),
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, ArithError> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '#' && c != '_' && c != '@')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Num(parse_number(expr, &rest[..len])?));
+            rest = &rest[len..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_owned()));
+            rest = &rest[len..];
+        } else if let Some(op) = OPS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(ArithError::Syntax(expr.to_owned(), rest.to_owned()));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+// decimal, `0x` hex, `0` octal and `base#digits`
+fn parse_number(expr: &str, text: &str) -> Result<i64, ArithError> {
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        match base.parse::<u32>() {
+            Ok(base) if (2..=64).contains(&base) => (base, digits),
+            _ => return Err(ArithError::Base(text.to_owned())),
+        }
+    } else if let Some(digits) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        (16, digits)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    if digits.is_empty() {
+        return Err(ArithError::Base(text.to_owned()));
+    }
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => u32::MAX,
+        };
+        if digit >= base {
+            return Err(ArithError::Base(expr.to_owned()));
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    expr: &'a str,
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn error_token(&self) -> String {
+        self.tokens
+            .get(self.pos)
+            .map(token_text)
+            .unwrap_or_default()
+    }
+
+    fn comma(&mut self) -> Result<Expr, ArithError> {
+        let mut left = self.assign()?;
+        while self.eat(",") {
+            let right = self.assign()?;
+            left = Expr::Binary(",", Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn assign(&mut self) -> Result<Expr, ArithError> {
+        if let (Some(Token::Name(name)), Some(Token::Op(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            if matches!(
+                *op,
+                "=" | "+=" | "-=" | "*=" | "/=" | "%=" | "<<=" | ">>=" | "&=" | "^=" | "|="
+            ) {
+                self.pos += 2;
+                let value = self.assign()?;
+                return Ok(Expr::Assign(name.clone(), op, Box::new(value)));
+            }
+        }
+        self.cond()
+    }
+
+    fn cond(&mut self) -> Result<Expr, ArithError> {
+        let test = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(test);
+        }
+        let then = self.comma()?;
+        if !self.eat(":") {
+            return Err(ArithError::Syntax(self.expr.to_owned(), self.error_token()));
+        }
+        let otherwise = self.cond()?;
+        Ok(Expr::Cond(
+            Box::new(test),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    // binary operators from the loosest to the tightest binding level
+    fn binary(&mut self, level: usize) -> Result<Expr, ArithError> {
+        const LEVELS: &[&[&str]] = &[
+            &["||"],
+            &["&&"],
+            &["|"],
+            &["^"],
+            &["&"],
+            &["==", "!="],
+            &["<", ">", "<=", ">="],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        let Some(ops) = LEVELS.get(level) else {
+            return self.power();
+        };
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.peek_op().filter(|op| ops.contains(op)) {
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn power(&mut self) -> Result<Expr, ArithError> {
+        let base = self.unary()?;
+        if self.eat("**") {
+            let exp = self.power()?;
+            return Ok(Expr::Binary("**", Box::new(base), Box::new(exp)));
+        }
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ArithError> {
+        match self.peek_op() {
+            Some(op @ ("++" | "--")) => {
+                self.pos += 1;
+                match self.tokens.get(self.pos) {
+                    Some(Token::Name(name)) => {
+                        self.pos += 1;
+                        Ok(Expr::PreStep(name.clone(), if op == "++" { 1 } else { -1 }))
+                    }
+                    // `--5` is two negations
+                    _ => {
+                        let sign = if op == "++" { "+" } else { "-" };
+                        let operand = Expr::Unary(sign, Box::new(self.unary()?));
+                        Ok(Expr::Unary(sign, Box::new(operand)))
+                    }
+                }
+            }
+            Some(op @ ("!" | "~" | "-" | "+")) => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.unary()?)))
+            }
+            _ => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, ArithError> {
+        let expr = self.primary()?;
+        if let Expr::Var(name) = &expr {
+            if self.eat("++") {
+                return Ok(Expr::PostStep(name.clone(), 1));
+            }
+            if self.eat("--") {
+                return Ok(Expr::PostStep(name.clone(), -1));
+            }
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ArithError> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Num(v)) => {
+                self.pos += 1;
+                Ok(Expr::Num(*v))
+            }
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                Ok(Expr::Var(name.clone()))
+            }
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let expr = self.comma()?;
+                if !self.eat(")") {
+                    return Err(ArithError::Syntax(self.expr.to_owned(), self.error_token()));
+                }
+                Ok(expr)
+            }
+            _ => Err(ArithError::Operand(
+                self.expr.to_owned(),
+                self.error_token(),
+            )),
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    shell: &'a mut Shell,
+    expr: &'a str,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn var(&mut self, name: &str) -> Result<i64, ArithError> {
+        let value = self.shell.vars.get(name).unwrap_or_default().to_owned();
+        if let Ok(v) = value.trim().parse() {
+            return Ok(v);
+        }
+        // variables may hold expressions of their own
+        eval_depth(self.shell, &value, self.depth + 1)
+    }
+
+    fn set(&mut self, name: &str, value: i64) -> Result<i64, ArithError> {
+        self.shell.vars.set(name, value.to_string())?;
+        Ok(value)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<i64, ArithError> {
+        match expr {
+            Expr::Num(v) => Ok(*v),
+            Expr::Var(name) => self.var(name),
+            Expr::Unary(op, operand) => {
+                let v = self.eval(operand)?;
+                Ok(match *op {
+                    "!" => (v == 0) as i64,
+                    "~" => !v,
+                    "-" => v.wrapping_neg(),
+                    _ => v,
+                })
+            }
+            Expr::Binary("&&", left, right) => {
+                Ok((self.eval(left)? != 0 && self.eval(right)? != 0) as i64)
+            }
+            Expr::Binary("||", left, right) => {
+                Ok((self.eval(left)? != 0 || self.eval(right)? != 0) as i64)
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                self.binary(op, left, right)
+            }
+            Expr::Assign(name, op, value) => {
+                let value = self.eval(value)?;
+                let value = match op.strip_suffix('=').unwrap_or_default() {
+                    "" => value,
+                    op => {
+                        let current = self.var(name)?;
+                        self.binary(op, current, value)?
+                    }
+                };
+                self.set(name, value)
+            }
+            Expr::PreStep(name, step) => {
+                let value = self.var(name)?.wrapping_add(*step);
+                self.set(name, value)
+            }
+            Expr::PostStep(name, step) => {
+                let value = self.var(name)?;
+                self.set(name, value.wrapping_add(*step))?;
+                Ok(value)
+            }
+            Expr::Cond(test, then, otherwise) => match self.eval(test)? {
+                0 => self.eval(otherwise),
+                _ => self.eval(then),
+            },
+        }
+    }
+
+    fn binary(&self, op: &str, left: i64, right: i64) -> Result<i64, ArithError> {
+        Ok(match op {
+            "," => right,
+            "|" => left | right,
+            "^" => left ^ right,
+            "&" => left & right,
+            "==" => (left == right) as i64,
+            "!=" => (left != right) as i64,
+            "<" => (left < right) as i64,
+            ">" => (left > right) as i64,
+            "<=" => (left <= right) as i64,
+            ">=" => (left >= right) as i64,
+            "<<" => left.wrapping_shl(right as u32),
+            ">>" => left.wrapping_shr(right as u32),
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "*" => left.wrapping_mul(right),
+            "/" | "%" if right == 0 => return Err(ArithError::DivideByZero(self.expr.to_owned())),
+            "/" => left.wrapping_div(right),
+            "%" => left.wrapping_rem(right),
+            "**" if right < 0 => {
+                return Err(ArithError::Syntax(
+                    self.expr.to_owned(),
+                    "exponent less than 0".to_owned(),
+                ))
+            }
+            "**" => left.wrapping_pow(right.min(u32::MAX as i64) as u32),
+            _ => unreachable!("unknown operator {}", op),
+        })
+    }
+}
@@ -0,0 +1,155 @@
+use crate::lexer::{Word, WordPart};
+
+#[derive(Debug, Clone)]
+enum Atom {
+    // an unquoted character, the only kind that can form braces
+    Char(char),
+    Part(WordPart),
+}
+
+// `a{b,c}d` -> `abd acd`, words without a valid brace expression are returned as they are
+pub fn expand(word: &Word) -> Vec<Word> {
+    if !word
+        .0
+        .iter()
+        .any(|part| matches!(part, WordPart::Literal(v) if v.contains('{')))
+    {
+        return vec![word.clone()];
+    }
+    let mut atoms = Vec::new();
+    for part in word.0.iter() {
+        match part {
+            WordPart::Literal(v) => atoms.extend(v.chars().map(Atom::Char)),
+            _ => atoms.push(Atom::Part(part.clone())),
+        }
+    }
+    expand_atoms(atoms).into_iter().map(to_word).collect()
+}
+
+fn to_word(atoms: Vec<Atom>) -> Word {
+    let mut parts = Vec::new();
+    for atom in atoms {
+        match (atom, parts.last_mut()) {
+            (Atom::Char(c), Some(WordPart::Literal(v))) => v.push(c),
+            (Atom::Char(c), _) => parts.push(WordPart::Literal(c.to_string())),
+            (Atom::Part(part), _) => parts.push(part),
+        }
+    }
+    Word(parts)
+}
+
+fn is_char(atom: &Atom, c: char) -> bool {
+    matches!(atom, Atom::Char(v) if *v == c)
+}
+
+fn expand_atoms(atoms: Vec<Atom>) -> Vec<Vec<Atom>> {
+    for start in 0..atoms.len() {
+        if !is_char(&atoms[start], '{') {
+            continue;
+        }
+        let Some((end, commas)) = find_close(&atoms, start) else {
+            continue;
+        };
+        let items = if commas.is_empty() {
+            match sequence(&atoms[start + 1..end]) {
+                Some(items) => items
+                    .into_iter()
+                    .map(|v| v.chars().map(Atom::Char).collect())
+                    .collect(),
+                None => continue,
+            }
+        } else {
+            let mut items = Vec::with_capacity(commas.len() + 1);
+            let mut from = start + 1;
+            for comma in commas.into_iter().chain([end]) {
+                items.push(atoms[from..comma].to_vec());
+                from = comma + 1;
+            }
+            items
+        };
+        let mut out = Vec::new();
+        for item in items {
+            let mut word = atoms[..start].to_vec();
+            word.extend(item);
+            word.extend_from_slice(&atoms[end + 1..]);
+            out.extend(expand_atoms(word));
+        }
+        return out;
+    }
+    vec![atoms]
+}
+
+// the `}` matching the `{` at `start` and the top level commas in between
+fn find_close(atoms: &[Atom], start: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    for (index, atom) in atoms.iter().enumerate().skip(start + 1) {
+        match atom {
+            Atom::Char('{') => depth += 1,
+            Atom::Char('}') if depth == 0 => return Some((index, commas)),
+            Atom::Char('}') => depth -= 1,
+            Atom::Char(',') if depth == 0 => commas.push(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+// `1..10`, `a..e`, `10..1..3` or `01..10`
+fn sequence(atoms: &[Atom]) -> Option<Vec<String>> {
+    let mut text = String::new();
+    for atom in atoms {
+        match atom {
+            Atom::Char(c) => text.push(*c),
+            Atom::Part(_) => return None,
+        }
+    }
+    let mut iter = text.split("..");
+    let (start, end) = (iter.next()?, iter.next()?);
+    let step = match iter.next() {
+        Some(step) => step.parse::<i64>().ok()?.unsigned_abs().max(1),
+        None => 1,
+    };
+    if iter.next().is_some() {
+        return None;
+    }
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = |v: &str| {
+            v.trim_start_matches('-').len() > 1 && v.trim_start_matches('-').starts_with('0')
+        };
+        let width = if padded(start) || padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        return Some(
+            range(first, last, step)
+                .into_iter()
+                .map(|v| match v < 0 {
+                    true => format!("-{:0>1$}", v.unsigned_abs(), width.saturating_sub(1)),
+                    false => format!("{:0>1$}", v, width),
+                })
+                .collect(),
+        );
+    }
+    let mut start = start.chars();
+    let mut end = end.chars();
+    match (start.next(), start.next(), end.next(), end.next()) {
+        (Some(first), None, Some(last), None) if first.is_ascii() && last.is_ascii() => Some(
+            range(first as i64, last as i64, step)
+                .into_iter()
+                .map(|v| (v as u8 as char).to_string())
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+fn range(first: i64, last: i64, step: u64) -> Vec<i64> {
+    let step = step as usize;
+    if first <= last {
+        (first..=last).step_by(step).collect()
+    } else {
+        (last..=first).rev().step_by(step).collect()
+    }
+}
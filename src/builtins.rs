@@ -0,0 +1,1413 @@
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+
+use crate::arith;
+use crate::complete::Spec;
+use crate::cond;
+use crate::shell::{self, Flow, Shell, SET_OPTIONS, SHOPT_NAMES};
+use crate::sys;
+use crate::vars::{self, Var};
+
+// the names the builtins are run by
+pub const NAMES: &[&str] = &[
+    ".", "[", "break", "cd", "complete", "continue", "disown", "echo", "eval", "exec", "exit",
+    "export", "fc", "getopts", "history", "kill", "let", "local", "pwd", "read", "readonly",
+    "return", "set", "shift", "shopt", "source", "test", "trap", "type", "unset", "wait",
+];
+
+const KILL_USAGE: &str =
+    "kill: usage: kill [-s sigspec | -n signum | -sigspec] pid | jobspec ... or kill -l [sigspec]";
+const READ_USAGE: &str = "read: usage: read [-r] [-p prompt] [name ...]";
+const TRAP_USAGE: &str = "trap: usage: trap [-p] [[action] signal_spec ...]";
+const COMPLETE_USAGE: &str =
+    "complete: usage: complete [-pr] [-W wordlist] [-F function] [name ...]";
+const HISTORY_USAGE: &str = "history: usage: history [-c] [-d offset] [n]";
+
+pub fn kill<W: Write, E: Write>(
+    shell: &Shell,
+    args: &[Cow<'_, str>],
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let mut sig = sys::SIGTERM;
+    let mut iter = args.iter().map(|v| v.as_ref()).peekable();
+    while let Some(arg) = iter.peek().copied() {
+        if !arg.starts_with('-') || arg == "-" {
+            break;
+        }
+        iter.next();
+        match arg {
+            "--" => break,
+            "-l" | "-L" => return kill_list(iter, stdout, stderr),
+            "-s" | "-n" => {
+                let Some(name) = iter.next() else {
+                    writeln!(stderr, "kill: {}: option requires an argument", arg)?;
+                    writeln!(stderr, "{}", KILL_USAGE)?;
+                    return Ok(2);
+                };
+                match sys::signal_from_str(name) {
+                    Some(v) => sig = v,
+                    None => {
+                        writeln!(stderr, "kill: {}: invalid signal specification", name)?;
+                        return Ok(1);
+                    }
+                }
+            }
+            _ => match sys::signal_from_str(&arg[1..]) {
+                Some(v) => sig = v,
+                None => {
+                    writeln!(stderr, "kill: {}: invalid signal specification", &arg[1..])?;
+                    return Ok(1);
+                }
+            },
+        }
+    }
+    if iter.peek().is_none() {
+        writeln!(stderr, "{}", KILL_USAGE)?;
+        return Ok(2);
+    }
+    let mut status = 0;
+    for target in iter {
+        let pid = if let Some(spec) = target.strip_prefix('%') {
+            match shell.jobs.find(spec) {
+                // background jobs lead their own process group
+                Some(job) => -job.pid,
+                None => {
+                    writeln!(stderr, "kill: {}: no such job", target)?;
+                    status = 1;
+                    continue;
+                }
+            }
+        } else {
+            match target.parse::<i32>() {
+                Ok(pid) => pid,
+                Err(_) => {
+                    writeln!(
+                        stderr,
+                        "kill: {}: arguments must be process or job IDs",
+                        target
+                    )?;
+                    status = 1;
+                    continue;
+                }
+            }
+        };
+        if let Err(err) = sys::kill(pid, sig) {
+            writeln!(stderr, "kill: ({}) - {}", pid.abs(), os_error(&err))?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+pub fn wait<E: Write>(shell: &mut Shell, args: &[Cow<'_, str>], stderr: &mut E) -> io::Result<i32> {
+    if args.is_empty() {
+        for id in shell.jobs.ids() {
+            shell.jobs.wait(id)?;
+        }
+        return Ok(0);
+    }
+    let mut status = 0;
+    for arg in args {
+        let id = if let Some(spec) = arg.strip_prefix('%') {
+            match shell.jobs.find(spec) {
+                Some(job) => job.id,
+                None => {
+                    writeln!(stderr, "wait: {}: no such job", arg)?;
+                    status = 127;
+                    continue;
+                }
+            }
+        } else {
+            match arg.parse::<i32>() {
+                Ok(pid) => match shell.jobs.find_pid(pid) {
+                    Some(job) => job.id,
+                    None => {
+                        writeln!(stderr, "wait: pid {} is not a child of this shell", pid)?;
+                        status = 127;
+                        continue;
+                    }
+                },
+                Err(_) => {
+                    writeln!(stderr, "wait: `{}': not a pid or valid job spec", arg)?;
+                    status = 1;
+                    continue;
+                }
+            }
+        };
+        status = shell.jobs.wait(id)?;
+    }
+    Ok(status)
+}
+
+// changes directory, keeping `PWD` and the `OLDPWD` that `cd -` goes back to
+pub fn cd<O: Write, E: Write>(
+    shell: &mut Shell,
+    path: &str,
+    stdout: &mut O,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let dir = match path {
+        "~" => shell.vars.get("HOME").unwrap_or("/").to_owned(),
+        "-" => match shell.vars.get("OLDPWD") {
+            Some(dir) => dir.to_owned(),
+            None => {
+                writeln!(stderr, "cd: OLDPWD not set")?;
+                return Ok(1);
+            }
+        },
+        _ => path.to_owned(),
+    };
+    let current = std::env::current_dir().map(|dir| dir.to_string_lossy().into_owned());
+    let old = shell.vars.get("PWD").map(str::to_owned).or(current.ok());
+    if std::env::set_current_dir(&dir).is_err() {
+        writeln!(stdout, "cd: {}: No such file or directory", dir)?;
+        return Ok(1);
+    }
+    if let Some(old) = old {
+        let _ = shell.vars.set("OLDPWD", old);
+    }
+    let pwd = std::env::current_dir()?.to_string_lossy().into_owned();
+    if path == "-" {
+        writeln!(stdout, "{}", pwd)?;
+    }
+    let _ = shell.vars.set("PWD", pwd);
+    Ok(0)
+}
+
+pub fn disown<E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let (flags, args) = match parse_flags(args, "har") {
+        Ok(v) => v,
+        Err(c) => {
+            writeln!(stderr, "disown: -{}: invalid option", c)?;
+            writeln!(
+                stderr,
+                "disown: usage: disown [-h] [-ar] [jobspec ... | pid ...]"
+            )?;
+            return Ok(2);
+        }
+    };
+    let nohup = flags.contains('h');
+    let all = flags.contains('a');
+    let running = flags.contains('r');
+    let mut iter = args.iter().map(|v| v.as_ref()).peekable();
+    let mut ids = Vec::new();
+    let mut status = 0;
+    if iter.peek().is_none() {
+        if all || running {
+            ids = if running {
+                shell.jobs.running_ids()
+            } else {
+                shell.jobs.ids()
+            };
+        } else {
+            match shell.jobs.find("+") {
+                Some(job) => ids.push(job.id),
+                None => {
+                    writeln!(stderr, "disown: current: no such job")?;
+                    status = 1;
+                }
+            }
+        }
+    }
+    for arg in iter {
+        let job = match arg.strip_prefix('%') {
+            Some(spec) => shell.jobs.find(spec),
+            None => arg.parse().ok().and_then(|pid| shell.jobs.find_pid(pid)),
+        };
+        match job {
+            Some(job) => ids.push(job.id),
+            None => {
+                writeln!(stderr, "disown: {}: no such job", arg)?;
+                status = 1;
+            }
+        }
+    }
+    for id in ids {
+        if nohup {
+            if let Some(job) = shell.jobs.get_mut(id) {
+                job.nohup = true;
+            }
+        } else {
+            shell.jobs.remove(id);
+        }
+    }
+    Ok(status)
+}
+
+pub fn export<W: Write, E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let (flags, args) = match parse_flags(args, "pn") {
+        Ok(v) => v,
+        Err(c) => {
+            writeln!(stderr, "export: -{}: invalid option", c)?;
+            writeln!(
+                stderr,
+                "export: usage: export [-n] [name[=value] ...] or export -p"
+            )?;
+            return Ok(2);
+        }
+    };
+    if args.is_empty() || flags.contains('p') {
+        print_vars(shell, stdout, |var| var.exported)?;
+        return Ok(0);
+    }
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_owned())),
+            None => (arg.as_ref(), None),
+        };
+        if !vars::is_name(name) {
+            writeln!(stderr, "export: `{}': not a valid identifier", arg)?;
+            status = 1;
+            continue;
+        }
+        let res = if flags.contains('n') {
+            let res = match value {
+                Some(value) => shell.vars.set(name, value),
+                None => Ok(()),
+            };
+            shell.vars.unexport(name);
+            res
+        } else {
+            shell.vars.export(name, value)
+        };
+        if let Err(err) = res {
+            writeln!(stderr, "export: {}", err)?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+pub fn local<W: Write, E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    if !shell.vars.has_scope() {
+        writeln!(stderr, "local: can only be used in a function")?;
+        return Ok(1);
+    }
+    if args.is_empty() {
+        for name in shell.vars.locals() {
+            let var = shell.vars.var(name).cloned().unwrap_or_default();
+            print_var(name, &var, stdout)?;
+        }
+        return Ok(0);
+    }
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_owned())),
+            None => (arg.as_ref(), None),
+        };
+        if !vars::is_name(name) {
+            writeln!(stderr, "local: `{}': not a valid identifier", arg)?;
+            status = 1;
+            continue;
+        }
+        if let Err(err) = shell.vars.local(name, value) {
+            writeln!(stderr, "local: {}", err)?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+pub fn unset<E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let (flags, args) = match parse_flags(args, "fv") {
+        Ok(v) => v,
+        Err(c) => {
+            writeln!(stderr, "unset: -{}: invalid option", c)?;
+            writeln!(stderr, "unset: usage: unset [-f] [-v] [name ...]")?;
+            return Ok(2);
+        }
+    };
+    let functions = flags.ends_with('f');
+    let variables = flags.ends_with('v');
+    let mut status = 0;
+    for name in args {
+        if functions {
+            shell.functions.remove(name.as_ref());
+            continue;
+        }
+        if !vars::is_name(name) {
+            writeln!(stderr, "unset: `{}': not a valid identifier", name)?;
+            status = 1;
+            continue;
+        }
+        // without `-v` a name that isn't a variable can still be a function
+        if !variables
+            && shell.vars.get(name).is_none()
+            && shell.functions.remove(name.as_ref()).is_some()
+        {
+            continue;
+        }
+        if shell.vars.unset(name).is_err() {
+            writeln!(stderr, "unset: {}: cannot unset: readonly variable", name)?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+pub fn readonly<W: Write, E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let (flags, args) = match parse_flags(args, "p") {
+        Ok(v) => v,
+        Err(c) => {
+            writeln!(stderr, "readonly: -{}: invalid option", c)?;
+            writeln!(
+                stderr,
+                "readonly: usage: readonly [name[=value] ...] or readonly -p"
+            )?;
+            return Ok(2);
+        }
+    };
+    if args.is_empty() || flags.contains('p') {
+        print_vars(shell, stdout, |var| var.readonly)?;
+        return Ok(0);
+    }
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_owned())),
+            None => (arg.as_ref(), None),
+        };
+        if !vars::is_name(name) {
+            writeln!(stderr, "readonly: `{}': not a valid identifier", arg)?;
+            status = 1;
+            continue;
+        }
+        if let Err(err) = shell.vars.readonly(name, value) {
+            writeln!(stderr, "readonly: {}", err)?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+pub fn shopt<W: Write, E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let (flags, args) = match parse_flags(args, "supq") {
+        Ok(v) => v,
+        Err(c) => {
+            writeln!(stderr, "shopt: -{}: invalid option", c)?;
+            writeln!(stderr, "shopt: usage: shopt [-pqsu] [optname ...]")?;
+            return Ok(2);
+        }
+    };
+    if flags.contains('s') && flags.contains('u') {
+        writeln!(
+            stderr,
+            "shopt: cannot set and unset shell options simultaneously"
+        )?;
+        return Ok(1);
+    }
+    let mut status = 0;
+    let mut names = Vec::with_capacity(args.len());
+    for arg in args {
+        match SHOPT_NAMES.iter().find(|name| **name == arg) {
+            Some(name) => names.push(*name),
+            None => {
+                writeln!(stderr, "shopt: {}: invalid shell option name", arg)?;
+                status = 1;
+            }
+        }
+    }
+    let (set, unset) = (flags.contains('s'), flags.contains('u'));
+    if !args.is_empty() && (set || unset) {
+        for name in names {
+            match set {
+                true => shell.shopts.insert(name),
+                false => shell.shopts.remove(name),
+            };
+        }
+        return Ok(status);
+    }
+    // with no names every option is listed, or only those on with `-s` and off with `-u`
+    let listed = args.is_empty();
+    if listed {
+        names = SHOPT_NAMES
+            .iter()
+            .copied()
+            .filter(|name| !(set || unset) || shell.shopt(name) == set)
+            .collect();
+    }
+    for name in names {
+        let on = shell.shopt(name);
+        if !on && !listed {
+            status = 1;
+        }
+        if flags.contains('q') {
+            continue;
+        }
+        if flags.contains('p') {
+            writeln!(stdout, "shopt {} {}", if on { "-s" } else { "-u" }, name)?;
+        } else {
+            writeln!(stdout, "{:<15}\t{}", name, if on { "on" } else { "off" })?;
+        }
+    }
+    Ok(status)
+}
+
+pub fn set<W: Write, E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    if args.is_empty() {
+        for (name, var) in shell.vars.iter() {
+            print_assignment(name, var, stdout)?;
+        }
+        return Ok(0);
+    }
+    let mut iter = args.iter();
+    // the words after the options, which replace the positional parameters
+    let mut rest = None;
+    while let Some(arg) = iter.next() {
+        let on = match arg.chars().next() {
+            Some('-') => true,
+            Some('+') => false,
+            _ => {
+                rest = Some(std::iter::once(arg).chain(iter).collect::<Vec<_>>());
+                break;
+            }
+        };
+        if arg == "--" {
+            rest = Some(iter.collect());
+            break;
+        }
+        // `-` ends the options too, turning off `-x`
+        if arg == "-" {
+            shell.options.remove("xtrace");
+            let args = iter.collect::<Vec<_>>();
+            rest = Some(args).filter(|args| !args.is_empty());
+            break;
+        }
+        for c in arg.chars().skip(1) {
+            let name = match c {
+                'o' => match iter.next() {
+                    Some(name) => match SET_OPTIONS.iter().find(|(_, v)| *v == name) {
+                        Some((_, name)) => *name,
+                        None => {
+                            writeln!(stderr, "set: {}: invalid option name", name)?;
+                            return Ok(1);
+                        }
+                    },
+                    None => {
+                        print_options(shell, on, stdout)?;
+                        continue;
+                    }
+                },
+                _ => match SET_OPTIONS.iter().find(|(v, _)| *v == Some(c)) {
+                    Some((_, name)) => *name,
+                    None => {
+                        writeln!(
+                            stderr,
+                            "set: {}{}: invalid option",
+                            arg.chars().next().unwrap_or('-'),
+                            c
+                        )?;
+                        let mut letters = SET_OPTIONS
+                            .iter()
+                            .filter_map(|(c, _)| *c)
+                            .collect::<Vec<_>>();
+                        letters.sort_by_key(|c| (c.is_ascii_uppercase(), *c));
+                        writeln!(
+                            stderr,
+                            "set: usage: set [-{}] [-o option-name] [--] [-] [arg ...]",
+                            letters.into_iter().collect::<String>()
+                        )?;
+                        return Ok(2);
+                    }
+                },
+            };
+            match on {
+                true => shell.options.insert(name),
+                false => shell.options.remove(name),
+            };
+            // the line editor has one set of keys at a time
+            match (on, name) {
+                (true, "vi") => shell.options.remove("emacs"),
+                (true, "emacs") => shell.options.remove("vi"),
+                _ => false,
+            };
+        }
+    }
+    if let Some(rest) = rest {
+        shell.args = rest.into_iter().map(|arg| arg.to_string()).collect();
+    }
+    Ok(0)
+}
+
+pub fn read<R: Read, E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stdin: &mut R,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let mut raw = false;
+    let mut prompt = None;
+    let mut iter = args.iter();
+    let mut names = Vec::new();
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            names.push(arg.as_ref());
+            names.extend(iter.by_ref().map(|v| v.as_ref()));
+            break;
+        }
+        if arg == "--" {
+            names.extend(iter.by_ref().map(|v| v.as_ref()));
+            break;
+        }
+        let mut chars = arg.chars().skip(1);
+        while let Some(c) = chars.next() {
+            match c {
+                'r' => raw = true,
+                // `-pPROMPT` or `-p PROMPT`
+                'p' => {
+                    let rest: String = chars.by_ref().collect();
+                    prompt = match rest.is_empty() {
+                        true => iter.next().map(|v| v.to_string()),
+                        false => Some(rest),
+                    };
+                    if prompt.is_none() {
+                        writeln!(stderr, "read: -p: option requires an argument")?;
+                        writeln!(stderr, "{}", READ_USAGE)?;
+                        return Ok(2);
+                    }
+                }
+                _ => {
+                    writeln!(stderr, "read: -{}: invalid option", c)?;
+                    writeln!(stderr, "{}", READ_USAGE)?;
+                    return Ok(2);
+                }
+            }
+        }
+    }
+    if let Some(name) = names.iter().find(|v| !vars::is_name(v)) {
+        writeln!(stderr, "read: `{}': not a valid identifier", name)?;
+        return Ok(1);
+    }
+    if let Some(prompt) = prompt {
+        write!(stderr, "{}", prompt)?;
+        stderr.flush()?;
+    }
+    let (line, eof) = read_line(stdin, raw)?;
+    let mut status = if eof { 1 } else { 0 };
+    let values = match names.is_empty() {
+        true => vec![("REPLY", line)],
+        false => {
+            let ifs = shell.vars.get("IFS").unwrap_or(" \t\n").to_owned();
+            names
+                .iter()
+                .copied()
+                .zip(split_fields(&line, &ifs, names.len()))
+                .collect()
+        }
+    };
+    for (name, value) in values {
+        if let Err(err) = shell.vars.set(name, value) {
+            writeln!(stderr, "read: {}", err)?;
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+// `break` and `continue`, told apart by `name`
+pub fn loop_control<E: Write>(
+    shell: &mut Shell,
+    name: &str,
+    args: &[Cow<'_, str>],
+    stderr: &mut E,
+) -> io::Result<i32> {
+    if shell.loops == 0 {
+        writeln!(
+            stderr,
+            "{}: only meaningful in a `for', `while', or `until' loop",
+            name
+        )?;
+        return Ok(0);
+    }
+    let count = match args {
+        [] => Ok(1),
+        [arg] => match arg.parse::<i64>() {
+            Ok(count) if count > 0 => Ok(count),
+            Ok(_) => Err(format!("{}: loop count out of range", arg)),
+            Err(_) => Err(format!("{}: numeric argument required", arg)),
+        },
+        _ => Err(String::from("too many arguments")),
+    };
+    match count {
+        Ok(count) => {
+            let count = usize::try_from(count).map_or(shell.loops, |v| v.min(shell.loops));
+            shell.flow = Some(match name {
+                "break" => Flow::Break(count),
+                _ => Flow::Continue(count),
+            });
+            Ok(0)
+        }
+        // a bad count leaves every loop
+        Err(err) => {
+            writeln!(stderr, "{}: {}", name, err)?;
+            shell.flow = Some(Flow::Break(shell.loops));
+            Ok(1)
+        }
+    }
+}
+
+pub fn return_<E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stderr: &mut E,
+) -> io::Result<i32> {
+    if !shell.vars.has_scope() && shell.sourced == 0 {
+        writeln!(
+            stderr,
+            "return: can only `return' from a function or sourced script"
+        )?;
+        return Ok(2);
+    }
+    shell.flow = Some(Flow::Return);
+    match args {
+        [] => Ok(shell.last_status),
+        [arg] => match arg.parse::<i64>() {
+            Ok(status) => Ok((status & 0xff) as i32),
+            Err(_) => {
+                writeln!(stderr, "return: {}: numeric argument required", arg)?;
+                Ok(2)
+            }
+        },
+        _ => {
+            writeln!(stderr, "return: too many arguments")?;
+            Ok(2)
+        }
+    }
+}
+
+// parses the next option from the positional parameters, or from `args` when there are
+// any, setting `name` to it and `OPTARG` to its argument
+pub fn getopts<E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let [optstring, name, args @ ..] = args else {
+        writeln!(stderr, "getopts: usage: getopts optstring name [arg ...]")?;
+        return Ok(2);
+    };
+    let words = match args.is_empty() {
+        true => shell.args.clone(),
+        false => args.iter().map(|arg| arg.to_string()).collect(),
+    };
+    // a leading `:` reports problems through `name` and `OPTARG` instead of messages
+    let (silent, optstring) = match optstring.strip_prefix(':') {
+        Some(optstring) => (true, optstring),
+        None => (false, optstring.as_ref()),
+    };
+    let report = !silent && shell.vars.get("OPTERR") != Some("0");
+    let mut index = shell
+        .vars
+        .get("OPTIND")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1)
+        .max(1);
+    // where the next option starts inside a word like `-abc`, forgotten when `OPTIND` moves
+    let mut pos = match shell.optpos {
+        (optind, pos) if optind == index => pos,
+        _ => 0,
+    };
+    let word = words.get(index - 1).map_or("", String::as_str);
+    let (option, optarg, status) =
+        if pos == 0 && (word == "--" || !word.starts_with('-') || word == "-") {
+            if word == "--" {
+                index += 1;
+            }
+            ('?', None, 1)
+        } else {
+            pos = pos.max(1);
+            let c = word[pos..].chars().next().unwrap_or_default();
+            pos += c.len_utf8();
+            let rest = &word[pos..];
+            if rest.is_empty() {
+                index += 1;
+                pos = 0;
+            }
+            let takes_arg = (optstring.find(c).filter(|_| c != ':'))
+                .map(|i| optstring[i + c.len_utf8()..].starts_with(':'));
+            match takes_arg {
+                None => {
+                    if report {
+                        writeln!(stderr, "{}: illegal option -- {}", shell.name, c)?;
+                    }
+                    ('?', silent.then(|| c.to_string()), 0)
+                }
+                Some(true) if !rest.is_empty() => {
+                    index += 1;
+                    pos = 0;
+                    (c, Some(rest.to_owned()), 0)
+                }
+                Some(true) => match words.get(index - 1) {
+                    Some(optarg) => {
+                        index += 1;
+                        (c, Some(optarg.clone()), 0)
+                    }
+                    None if silent => (':', Some(c.to_string()), 0),
+                    None => {
+                        if report {
+                            writeln!(
+                                stderr,
+                                "{}: option requires an argument -- {}",
+                                shell.name, c
+                            )?;
+                        }
+                        ('?', None, 0)
+                    }
+                },
+                Some(false) => (c, None, 0),
+            }
+        };
+    shell.optpos = (index, pos);
+    let optarg = match optarg {
+        Some(optarg) => shell.vars.set("OPTARG", optarg),
+        None => shell.vars.unset("OPTARG"),
+    };
+    let results = [
+        shell.vars.set(name, option.to_string()),
+        shell.vars.set("OPTIND", index.to_string()),
+        optarg,
+    ];
+    if let Some(err) = results.into_iter().find_map(Result::err) {
+        writeln!(stderr, "getopts: {}", err)?;
+        return Ok(2);
+    }
+    Ok(status)
+}
+
+// `trap action sig...` runs `action` when a signal arrives, `''` ignores the signals and
+// `-` (or no action) puts back their default
+pub fn trap<W: Write, E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let mut args = args;
+    let mut print = false;
+    match args.first().map(|arg| arg.as_ref()) {
+        Some("-p") => {
+            print = true;
+            args = &args[1..];
+        }
+        Some("--") => args = &args[1..],
+        Some(arg) if arg.starts_with('-') && arg != "-" => {
+            writeln!(stderr, "trap: {}: invalid option", arg)?;
+            writeln!(stderr, "{}", TRAP_USAGE)?;
+            return Ok(2);
+        }
+        _ => {}
+    }
+    let parse = |arg: &str| match arg {
+        "EXIT" => Some(shell::EXIT_TRAP),
+        "ERR" => Some(shell::ERR_TRAP),
+        _ => sys::signal_from_str(arg),
+    };
+    let mut status = 0;
+    if print || args.is_empty() {
+        let mut sigs = Vec::new();
+        for arg in args {
+            match parse(arg) {
+                Some(sig) => sigs.push(sig),
+                None => {
+                    writeln!(stderr, "trap: {}: invalid signal specification", arg)?;
+                    status = 1;
+                }
+            }
+        }
+        for (sig, command) in &shell.traps {
+            if sigs.is_empty() || sigs.contains(sig) {
+                let name = match *sig {
+                    shell::EXIT_TRAP => "EXIT".to_owned(),
+                    shell::ERR_TRAP => "ERR".to_owned(),
+                    sig => format!("SIG{}", sys::signal_name(sig).unwrap_or_default()),
+                };
+                let command = format!("'{}'", command.replace('\'', "'\\''"));
+                writeln!(stdout, "trap -- {} {}", command, name)?;
+            }
+        }
+        return Ok(status);
+    }
+    // a lone signal is reset, as it is after `-`
+    let (action, sigs) = match args {
+        [_] => (None, args),
+        [action, sigs @ ..] if action == "-" => (None, sigs),
+        [action, sigs @ ..] => (Some(action.as_ref()), sigs),
+        [] => unreachable!(),
+    };
+    for arg in sigs {
+        let Some(sig) = parse(arg) else {
+            writeln!(stderr, "trap: {}: invalid signal specification", arg)?;
+            status = 1;
+            continue;
+        };
+        match action {
+            None => {
+                shell.traps.remove(&sig);
+            }
+            Some(command) => {
+                shell.traps.insert(sig, command.to_owned());
+            }
+        }
+        // the pseudo-signals are run by the shell itself
+        if sig == shell::EXIT_TRAP || sig == shell::ERR_TRAP {
+            continue;
+        }
+        match action {
+            None => shell.reset_signal(sig),
+            Some("") => sys::ignore_signal(sig),
+            Some(_) => sys::catch_signal(sig),
+        }
+    }
+    Ok(status)
+}
+
+// `complete -W words name...` and `complete -F function name...` set how the line editor
+// completes the arguments of the named commands, `-r` removes that and `-p` (or nothing)
+// prints it
+pub fn complete<W: Write, E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let (mut print, mut remove, mut spec) = (false, false, None);
+    let mut args = args.iter().map(|arg| arg.as_ref()).peekable();
+    while let Some(arg) = args.next_if(|arg| arg.starts_with('-') && arg.len() > 1) {
+        if arg == "--" {
+            break;
+        }
+        for c in arg[1..].chars() {
+            match c {
+                'p' => print = true,
+                'r' => remove = true,
+                'W' | 'F' => {
+                    let Some(value) = args.next() else {
+                        writeln!(stderr, "complete: -{}: option requires an argument", c)?;
+                        writeln!(stderr, "{}", COMPLETE_USAGE)?;
+                        return Ok(2);
+                    };
+                    spec = Some(match c {
+                        'W' => Spec::Words(value.to_owned()),
+                        _ => Spec::Function(value.to_owned()),
+                    });
+                }
+                _ => {
+                    writeln!(stderr, "complete: -{}: invalid option", c)?;
+                    writeln!(stderr, "{}", COMPLETE_USAGE)?;
+                    return Ok(2);
+                }
+            }
+        }
+    }
+    let names = args.collect::<Vec<_>>();
+    if remove {
+        match names.is_empty() {
+            true => shell.completions.clear(),
+            false => shell
+                .completions
+                .retain(|name, _| !names.contains(&name.as_str())),
+        }
+        return Ok(0);
+    }
+    if let (Some(spec), false) = (&spec, print) {
+        for name in names {
+            shell.completions.insert(name.to_owned(), spec.clone());
+        }
+        return Ok(0);
+    }
+    let mut status = 0;
+    let print_spec = |stdout: &mut W, name: &str, spec: &Spec| match spec {
+        Spec::Words(words) => writeln!(
+            stdout,
+            "complete -W '{}' {}",
+            words.replace('\'', "'\\''"),
+            name
+        ),
+        Spec::Function(function) => writeln!(stdout, "complete -F {} {}", function, name),
+    };
+    if names.is_empty() {
+        for (name, spec) in &shell.completions {
+            print_spec(stdout, name, spec)?;
+        }
+    }
+    for name in names {
+        match shell.completions.get(name) {
+            Some(spec) => print_spec(stdout, name, spec)?,
+            None => {
+                writeln!(stderr, "complete: {}: no completion specification", name)?;
+                status = 1;
+            }
+        }
+    }
+    Ok(status)
+}
+
+// lists the history with each entry's number, or the last `n` entries; `-c` clears it and
+// `-d offset` deletes one entry, counting back from the end when negative
+pub fn history<W: Write, E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let mut clear = false;
+    let mut delete = None;
+    let mut args = args.iter();
+    let mut rest = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--" => {
+                rest.extend(args.by_ref());
+                break;
+            }
+            "-c" => clear = true,
+            "-d" => match args.next() {
+                Some(offset) => delete = Some(offset),
+                None => {
+                    writeln!(stderr, "history: -d: option requires an argument")?;
+                    writeln!(stderr, "{}", HISTORY_USAGE)?;
+                    return Ok(2);
+                }
+            },
+            arg if arg.starts_with('-') && arg.len() > 1 => {
+                writeln!(stderr, "history: {}: invalid option", arg)?;
+                writeln!(stderr, "{}", HISTORY_USAGE)?;
+                return Ok(2);
+            }
+            _ => rest.push(arg),
+        }
+    }
+    let history = &mut shell.history;
+    if clear {
+        history.clear();
+    }
+    if let Some(offset) = delete {
+        let index = match offset.parse::<i64>() {
+            Ok(back @ ..=-1) => history.len().checked_sub(back.unsigned_abs() as usize),
+            Ok(number) => (number as usize).checked_sub(history.number(0)),
+            Err(_) => None,
+        };
+        match index.filter(|&index| index < history.len()) {
+            Some(index) => history.remove(index),
+            None => {
+                writeln!(stderr, "history: {}: history position out of range", offset)?;
+                return Ok(1);
+            }
+        }
+    }
+    if clear || delete.is_some() {
+        return Ok(0);
+    }
+    let count = match rest.as_slice() {
+        [] => history.len(),
+        [count] => match count.parse::<usize>() {
+            Ok(count) => count.min(history.len()),
+            Err(_) => {
+                writeln!(stderr, "history: {}: numeric argument required", count)?;
+                return Ok(1);
+            }
+        },
+        _ => {
+            writeln!(stderr, "history: too many arguments")?;
+            return Ok(1);
+        }
+    };
+    let format = shell.vars.get("HISTTIMEFORMAT");
+    for index in history.len() - count..history.len() {
+        let entry = history.get(index).unwrap_or_default();
+        // bash shows `??` for entries without a time
+        let time = match (format, history.time(index)) {
+            (Some(format), Some(time)) => sys::format_time(format, time),
+            (Some(_), None) => "??".to_owned(),
+            (None, _) => String::new(),
+        };
+        writeln!(stdout, "{:5}  {}{}", history.number(index), time, entry)?;
+    }
+    Ok(0)
+}
+
+// drops the first `n` positional parameters, failing without a change when there are fewer
+pub fn shift<E: Write>(
+    shell: &mut Shell,
+    args: &[Cow<'_, str>],
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let count = match args {
+        [] => 1,
+        [arg] => match arg.parse::<i64>() {
+            Ok(count) if count >= 0 => count as usize,
+            Ok(_) => {
+                writeln!(stderr, "shift: {}: shift count out of range", arg)?;
+                return Ok(1);
+            }
+            Err(_) => {
+                writeln!(stderr, "shift: {}: numeric argument required", arg)?;
+                return Ok(1);
+            }
+        },
+        _ => {
+            writeln!(stderr, "shift: too many arguments")?;
+            return Ok(1);
+        }
+    };
+    if count > shell.args.len() {
+        return Ok(1);
+    }
+    shell.args.drain(..count);
+    Ok(0)
+}
+
+// evaluates each argument as an arithmetic expression, succeeding when the last isn't 0
+pub fn let_<E: Write>(shell: &mut Shell, args: &[Cow<'_, str>], stderr: &mut E) -> io::Result<i32> {
+    if args.is_empty() {
+        writeln!(stderr, "let: expression expected")?;
+        return Ok(1);
+    }
+    let mut value = 0;
+    for arg in args {
+        match arith::eval(shell, arg) {
+            Ok(v) => value = v,
+            Err(err) => {
+                writeln!(stderr, "let: {}", err)?;
+                return Ok(1);
+            }
+        }
+    }
+    Ok((value == 0) as i32)
+}
+
+// `test` and `[`, which also wants a closing `]`
+pub fn test<E: Write>(
+    shell: &Shell,
+    name: &str,
+    args: &[Cow<'_, str>],
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let mut args = args.iter().map(|v| v.as_ref()).collect::<Vec<_>>();
+    if name == "[" && args.pop() != Some("]") {
+        writeln!(stderr, "[: missing `]'")?;
+        return Ok(2);
+    }
+    match test_args(shell, &args) {
+        Ok(true) => Ok(0),
+        Ok(false) => Ok(1),
+        Err(err) => {
+            writeln!(stderr, "{}: {}", name, err)?;
+            Ok(2)
+        }
+    }
+}
+
+// short expressions go by the number of arguments the way POSIX describes, so `[ ! = x ]`
+// compares two strings
+fn test_args(shell: &Shell, args: &[&str]) -> Result<bool, String> {
+    match *args {
+        [] => Ok(false),
+        [arg] => Ok(!arg.is_empty()),
+        ["!", arg] => Ok(arg.is_empty()),
+        [op, arg] if cond::UNARY.contains(&op) => Ok(cond::unary(shell, op, arg)),
+        [op, _] => Err(format!("{}: unary operator expected", op)),
+        [left, op, right] if is_test_binary(op) => test_binary(left, op, right),
+        ["!", ..] if args.len() <= 4 => Ok(!test_args(shell, &args[1..])?),
+        ["(", .., ")"] if args.len() <= 4 => test_args(shell, &args[1..args.len() - 1]),
+        [_, op, _] => Err(format!("{}: binary operator expected", op)),
+        _ => {
+            let mut parser = TestParser {
+                shell,
+                args,
+                pos: 0,
+            };
+            let value = parser.or()?;
+            match parser.args.get(parser.pos) {
+                Some(_) => Err(String::from("too many arguments")),
+                None => Ok(value),
+            }
+        }
+    }
+}
+
+fn is_test_binary(op: &str) -> bool {
+    matches!(op, "-a" | "-o") || cond::BINARY.contains(&op)
+}
+
+fn test_binary(left: &str, op: &str, right: &str) -> Result<bool, String> {
+    let number = |value: &str| {
+        value
+            .trim()
+            .parse::<i64>()
+            .map_err(|_| format!("{}: integer expression expected", value))
+    };
+    Ok(match op {
+        "-a" => !left.is_empty() && !right.is_empty(),
+        "-o" => !left.is_empty() || !right.is_empty(),
+        op if cond::is_numeric(op) => cond::compare(number(left)?, op, number(right)?),
+        op => cond::binary(left, op, right),
+    })
+}
+
+// longer expressions, where `-o` binds looser than `-a`, which binds looser than `!`
+struct TestParser<'a> {
+    shell: &'a Shell,
+    args: &'a [&'a str],
+    pos: usize,
+}
+
+impl TestParser<'_> {
+    fn or(&mut self) -> Result<bool, String> {
+        let mut value = self.and()?;
+        while self.eat("-o") {
+            value |= self.and()?;
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut value = self.not()?;
+        while self.eat("-a") {
+            value &= self.not()?;
+        }
+        Ok(value)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        if self.eat("!") {
+            return Ok(!self.not()?);
+        }
+        if self.eat("(") {
+            let value = self.or()?;
+            if !self.eat(")") {
+                return Err(String::from("`)' expected"));
+            }
+            return Ok(value);
+        }
+        let rest = &self.args[self.pos..];
+        let (value, used) = match *rest {
+            [] => return Err(String::from("argument expected")),
+            [left, op, right, ..] if is_test_binary(op) && !matches!(op, "-a" | "-o") => {
+                (test_binary(left, op, right)?, 3)
+            }
+            [op, arg, ..] if cond::UNARY.contains(&op) => (cond::unary(self.shell, op, arg), 2),
+            [arg, ..] => (!arg.is_empty(), 1),
+        };
+        self.pos += used;
+        Ok(value)
+    }
+
+    fn eat(&mut self, arg: &str) -> bool {
+        let found = self.args.get(self.pos) == Some(&arg);
+        self.pos += found as usize;
+        found
+    }
+}
+
+// one byte at a time so nothing past the line is taken from a shared stdin, the flag is
+// set when the input ended before a newline
+fn read_line<R: Read>(stdin: &mut R, raw: bool) -> io::Result<(String, bool)> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    let mut next = |stdin: &mut R| match stdin.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok::<_, io::Error>(Some(byte[0])),
+    };
+    let eof = loop {
+        match next(stdin)? {
+            None => break true,
+            Some(b'\n') => break false,
+            // without `-r` a backslash quotes the next character and joins lines
+            Some(b'\\') if !raw => match next(stdin)? {
+                None => break true,
+                Some(b'\n') => {}
+                Some(c) => line.push(c),
+            },
+            Some(c) => line.push(c),
+        }
+    };
+    Ok((String::from_utf8_lossy(&line).into_owned(), eof))
+}
+
+// `count` fields split on `ifs`, the last one keeps the rest of the line
+fn split_fields(line: &str, ifs: &str, count: usize) -> Vec<String> {
+    let is_space = |c: char| ifs.contains(c) && c.is_ascii_whitespace();
+    let mut rest = line.trim_matches(is_space);
+    let mut fields = Vec::with_capacity(count);
+    while fields.len() + 1 < count {
+        let Some(end) = rest.find(|c: char| ifs.contains(c)) else {
+            break;
+        };
+        fields.push(rest[..end].to_owned());
+        rest = rest[end..].trim_start_matches(is_space);
+        // a separator that isn't whitespace ends one field by itself
+        if let Some(c) = rest.chars().next().filter(|c| ifs.contains(*c)) {
+            rest = rest[c.len_utf8()..].trim_start_matches(is_space);
+        }
+    }
+    fields.push(rest.to_owned());
+    fields.resize(count, String::new());
+    fields
+}
+
+// `set -o` lists the options in a table, `set +o` as the commands that restore them
+fn print_options<W: Write>(shell: &Shell, table: bool, stdout: &mut W) -> io::Result<()> {
+    for (_, name) in SET_OPTIONS {
+        let on = shell.option(name);
+        if table {
+            writeln!(stdout, "{:<15}\t{}", name, if on { "on" } else { "off" })?;
+        } else {
+            writeln!(stdout, "set {}o {}", if on { '-' } else { '+' }, name)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_vars<W: Write>(shell: &Shell, stdout: &mut W, filter: fn(&Var) -> bool) -> io::Result<()> {
+    for (name, var) in shell.vars.iter().filter(|(_, var)| filter(var)) {
+        print_var(name, var, stdout)?;
+    }
+    Ok(())
+}
+
+// `name=value`, the way `set` lists variables
+fn print_assignment<W: Write>(name: &str, var: &Var, stdout: &mut W) -> io::Result<()> {
+    if !var.elements.is_empty() {
+        write!(stdout, "{}=(", name)?;
+        let values = var.value.iter().chain(&var.elements);
+        for (i, value) in values.enumerate() {
+            let sep = if i > 0 { " " } else { "" };
+            write!(stdout, "{}[{}]={}", sep, i, vars::double_quote(value))?;
+        }
+        return writeln!(stdout, ")");
+    }
+    match &var.value {
+        Some(value) => writeln!(stdout, "{}={}", name, vars::single_quote(value)),
+        None => Ok(()),
+    }
+}
+
+fn print_var<W: Write>(name: &str, var: &Var, stdout: &mut W) -> io::Result<()> {
+    if !var.elements.is_empty() {
+        write!(stdout, "declare {} {}=(", var.flags(), name)?;
+        let values = var.value.iter().chain(&var.elements);
+        for (i, value) in values.enumerate() {
+            let sep = if i > 0 { " " } else { "" };
+            write!(stdout, "{}[{}]={}", sep, i, vars::double_quote(value))?;
+        }
+        return writeln!(stdout, ")");
+    }
+    match &var.value {
+        Some(value) => writeln!(
+            stdout,
+            "declare {} {}={}",
+            var.flags(),
+            name,
+            vars::double_quote(value)
+        ),
+        None => writeln!(stdout, "declare {} {}", var.flags(), name),
+    }
+}
+
+// splits leading `-abc` flags off `args`, `Err` holds the first flag not in `valid`
+fn parse_flags<'a, 'b>(
+    args: &'a [Cow<'b, str>],
+    valid: &str,
+) -> Result<(String, &'a [Cow<'b, str>]), char> {
+    let mut flags = String::new();
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        if !arg.starts_with('-') || arg == "-" {
+            break;
+        }
+        index += 1;
+        if arg == "--" {
+            break;
+        }
+        for c in arg.chars().skip(1) {
+            if !valid.contains(c) {
+                return Err(c);
+            }
+            flags.push(c);
+        }
+    }
+    Ok((flags, &args[index..]))
+}
+
+fn kill_list<'a, W: Write, E: Write>(
+    mut args: impl Iterator<Item = &'a str>,
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let mut status = 0;
+    let mut any = false;
+    for arg in args.by_ref() {
+        any = true;
+        match arg.parse::<i32>() {
+            // exit statuses of signaled processes are 128 + signal
+            Ok(num) => match sys::signal_name(if num > 128 { num - 128 } else { num }) {
+                Some(name) => writeln!(stdout, "{}", name)?,
+                None => {
+                    writeln!(stderr, "kill: {}: invalid signal specification", arg)?;
+                    status = 1;
+                }
+            },
+            Err(_) => match sys::signal_from_str(arg) {
+                Some(num) => writeln!(stdout, "{}", num)?,
+                None => {
+                    writeln!(stderr, "kill: {}: invalid signal specification", arg)?;
+                    status = 1;
+                }
+            },
+        }
+    }
+    if !any {
+        let names = sys::SIGNALS
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        writeln!(stdout, "{}", names.join(" "))?;
+    }
+    Ok(status)
+}
+
+// `No such process (os error 3)` -> `No such process`
+pub fn os_error(err: &io::Error) -> String {
+    let msg = err.to_string();
+    match msg.find(" (os error") {
+        Some(index) => msg[..index].to_owned(),
+        None => msg,
+    }
+}
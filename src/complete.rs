@@ -0,0 +1,285 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::time::SystemTime;
+
+use crate::builtins;
+use crate::shell::Shell;
+use crate::vars;
+
+// the words after which a command name comes next
+pub const COMMAND_KEYWORDS: &[&str] = &["if", "then", "else", "elif", "while", "until", "do", "!"];
+
+// what the word before the cursor could be completed to
+#[derive(Debug)]
+pub struct Completion {
+    // how many characters of the line before the cursor the word takes up
+    pub len: usize,
+    // the text that could replace the word, sorted
+    pub candidates: Vec<String>,
+}
+
+// how `complete` was told to complete a command's arguments
+#[derive(Debug, Clone)]
+pub enum Spec {
+    // `-W`, the words in the list
+    Words(String),
+    // `-F`, what the function leaves in `COMPREPLY`
+    Function(String),
+}
+
+// finds completions, keeping the executables on `PATH` between uses
+#[derive(Debug, Default)]
+pub struct Completer {
+    commands: Vec<String>,
+    // the `PATH` directories `commands` were read from, with when each last changed
+    dirs: Vec<(String, Option<SystemTime>)>,
+}
+
+impl Completer {
+    pub fn complete(&mut self, shell: &mut Shell, before: &str) -> Completion {
+        let words = command_words(before);
+        let (word, earlier) = words.split_last().unwrap_or((&"", &[]));
+        // the words before it that aren't keywords, starting with the command
+        let args = match earlier.iter().position(|w| !COMMAND_KEYWORDS.contains(w)) {
+            Some(command) => &earlier[command..],
+            None => &[],
+        };
+        let spec = args
+            .first()
+            .and_then(|command| shell.completions.get(*command));
+        let mut candidates = if let Some(vars) = variables(shell, word) {
+            vars
+        } else if let Some(spec) = spec.cloned() {
+            generate(shell, &spec, args, word, before)
+        } else if args.is_empty() && !word.contains('/') {
+            let path = shell.vars.get("PATH").unwrap_or_default();
+            let names = (builtins::NAMES.iter().copied())
+                .chain(shell.functions.keys().map(String::as_str))
+                .chain(self.path_commands(path).iter().map(String::as_str));
+            names
+                .filter(|name| name.starts_with(word))
+                .map(str::to_owned)
+                .collect()
+        } else {
+            paths(shell, word)
+        };
+        candidates.sort();
+        candidates.dedup();
+        Completion {
+            len: word.chars().count(),
+            candidates,
+        }
+    }
+
+    // whether `name` is a builtin, a function or an executable, on `PATH` when it has no `/`
+    pub fn is_command(&mut self, shell: &Shell, name: &str) -> bool {
+        if name.contains('/') {
+            return fs::metadata(name).is_ok_and(|meta| is_executable(&meta));
+        }
+        let path = shell.vars.get("PATH").unwrap_or_default();
+        builtins::NAMES.contains(&name)
+            || shell.functions.contains_key(name)
+            || self
+                .path_commands(path)
+                .iter()
+                .any(|command| command == name)
+    }
+
+    // the executables in the `PATH` directories, read again once one of them changes
+    fn path_commands(&mut self, path: &str) -> &[String] {
+        let dirs = (path.split(':'))
+            .map(|dir| {
+                let modified = fs::metadata(dir).and_then(|meta| meta.modified());
+                (dir.to_owned(), modified.ok())
+            })
+            .collect::<Vec<_>>();
+        if dirs != self.dirs {
+            self.commands.clear();
+            for (dir, _) in &dirs {
+                let Ok(entries) = fs::read_dir(dir) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    if entry.metadata().is_ok_and(|meta| is_executable(&meta)) {
+                        self.commands
+                            .push(entry.file_name().to_string_lossy().into_owned());
+                    }
+                }
+            }
+            self.dirs = dirs;
+        }
+        &self.commands
+    }
+}
+
+pub fn is_executable(meta: &fs::Metadata) -> bool {
+    meta.is_file() && meta.permissions().mode() & 0o111 != 0
+}
+
+// the completions `spec` gives for `word` after the words `args`, a function is run with the
+// command, the word and the word before it as arguments, and `COMP_WORDS`, `COMP_CWORD`,
+// `COMP_LINE` and `COMP_POINT` describing the line
+fn generate(shell: &mut Shell, spec: &Spec, args: &[&str], word: &str, line: &str) -> Vec<String> {
+    match spec {
+        Spec::Words(words) => {
+            let word = unquote(word);
+            (words.split_whitespace())
+                .filter(|candidate| candidate.starts_with(&word))
+                .map(str::to_owned)
+                .collect()
+        }
+        Spec::Function(function) => {
+            let mut words = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+            words.push(word.to_owned());
+            let _ = shell.vars.set_array("COMP_WORDS", words);
+            let _ = shell.vars.set("COMP_CWORD", args.len().to_string());
+            let _ = shell.vars.set("COMP_LINE", line.to_owned());
+            let _ = shell.vars.set("COMP_POINT", line.len().to_string());
+            let _ = shell.vars.unset("COMPREPLY");
+            let previous = args.last().copied().unwrap_or_default();
+            let command = format!(
+                "{} {} {} {}",
+                function,
+                vars::single_quote(args.first().copied().unwrap_or_default()),
+                vars::single_quote(word),
+                vars::single_quote(previous)
+            );
+            let _ = crate::run_line(shell, &command);
+            // with no way to assign an array, a plain `COMPREPLY` holds the words separated by
+            // spaces
+            let reply = match shell.vars.elements("COMPREPLY").as_slice() {
+                [words] => words.split_whitespace().map(str::to_owned).collect(),
+                words => words.iter().map(|word| word.to_string()).collect(),
+            };
+            for name in [
+                "COMP_WORDS",
+                "COMP_CWORD",
+                "COMP_LINE",
+                "COMP_POINT",
+                "COMPREPLY",
+            ] {
+                let _ = shell.vars.unset(name);
+            }
+            reply
+        }
+    }
+}
+
+// the variables a `word` ending in `$name` or `${name` could refer to, `None` when it
+// doesn't end like that
+fn variables(shell: &Shell, word: &str) -> Option<Vec<String>> {
+    let dollar = word.rfind('$')?;
+    let (head, name) = word.split_at(dollar + 1);
+    let (head, name, close) = match name.strip_prefix('{') {
+        Some(name) => (&word[..dollar + 2], name, "}"),
+        None => (head, name, ""),
+    };
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    let names = (shell.vars.iter())
+        .map(|(var, _)| var)
+        .filter(|var| var.starts_with(name))
+        .map(|var| format!("{}{}{}", head, var, close));
+    Some(names.collect())
+}
+
+// the files and directories `word` could name, written the way `word` is, with `~` and any
+// opening quote kept, and a `/` after directories
+fn paths(shell: &Shell, word: &str) -> Vec<String> {
+    let quote = word.chars().next().filter(|c| *c == '\'' || *c == '"');
+    let typed = unquote(word);
+    let (dir, prefix) = match typed.rfind('/') {
+        Some(slash) => typed.split_at(slash + 1),
+        None => ("", typed.as_str()),
+    };
+    let home = shell.vars.get("HOME").unwrap_or_default();
+    let search = match dir {
+        "" => ".".to_owned(),
+        dir if dir.starts_with("~/") => format!("{}{}", home, &dir[1..]),
+        dir => dir.to_owned(),
+    };
+    let Ok(entries) = fs::read_dir(search) else {
+        return Vec::new();
+    };
+    let mut paths = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // hidden files only when asked for
+        if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+            continue;
+        }
+        let is_dir = fs::metadata(entry.path()).is_ok_and(|meta| meta.is_dir());
+        let path = format!("{}{}", dir, name);
+        let mut path = match quote {
+            Some(quote) => format!("{}{}", quote, path),
+            None => escape(&path),
+        };
+        match (is_dir, quote) {
+            (true, _) => path.push('/'),
+            (false, Some(quote)) => path.push(quote),
+            (false, None) => {}
+        }
+        paths.push(path);
+    }
+    paths
+}
+
+// `word` without the quotes and backslashes written in it
+fn unquote(word: &str) -> String {
+    let mut text = String::new();
+    let mut quote = None;
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => text.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '\\') => text.extend(chars.next()),
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+// `path` with a backslash before each character the shell would otherwise treat specially
+fn escape(path: &str) -> String {
+    let mut text = String::new();
+    for c in path.chars() {
+        if c.is_whitespace() || "'\"\\$`&|;()<>*?[]{}!#".contains(c) {
+            text.push('\\');
+        }
+        text.push(c);
+    }
+    text
+}
+
+// the words of the command being typed at the end of `before`, split at unquoted spaces and
+// starting after the last operator; the last is the word under the cursor, maybe empty
+fn command_words(before: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut chars = before.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '\\') => {
+                chars.next();
+            }
+            (None, _) if c.is_whitespace() || ";|&()<>".contains(c) => {
+                if ";|&(".contains(c) {
+                    words.clear();
+                } else if i > start {
+                    words.push(&before[start..i]);
+                }
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    words.push(&before[start..]);
+    words
+}
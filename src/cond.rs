@@ -0,0 +1,92 @@
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::time::SystemTime;
+
+use crate::shell::Shell;
+use crate::sys;
+
+// the operators `test` and `[[ ]]` share
+pub const UNARY: &[&str] = &[
+    "-a", "-b", "-c", "-d", "-e", "-f", "-g", "-h", "-k", "-n", "-o", "-p", "-r", "-s", "-t", "-u",
+    "-v", "-w", "-x", "-z", "-G", "-L", "-N", "-O", "-S",
+];
+pub const BINARY: &[&str] = &[
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+pub fn is_numeric(op: &str) -> bool {
+    matches!(op, "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge")
+}
+
+pub fn unary(shell: &Shell, op: &str, arg: &str) -> bool {
+    match op {
+        "-n" => !arg.is_empty(),
+        "-z" => arg.is_empty(),
+        "-o" => shell.option(arg),
+        "-v" => shell.vars.get(arg).is_some(),
+        "-t" => arg.parse().is_ok_and(sys::isatty),
+        "-r" => sys::access(arg, 4),
+        "-w" => sys::access(arg, 2),
+        "-x" => sys::access(arg, 1),
+        "-h" | "-L" => fs::symlink_metadata(arg).is_ok_and(|meta| meta.is_symlink()),
+        _ => fs::metadata(arg).is_ok_and(|meta| file_test(op, &meta)),
+    }
+}
+
+fn file_test(op: &str, meta: &Metadata) -> bool {
+    let kind = meta.file_type();
+    let mode = meta.permissions().mode();
+    match op {
+        "-a" | "-e" => true,
+        "-b" => kind.is_block_device(),
+        "-c" => kind.is_char_device(),
+        "-d" => kind.is_dir(),
+        "-f" => kind.is_file(),
+        "-p" => kind.is_fifo(),
+        "-S" => kind.is_socket(),
+        "-s" => meta.len() > 0,
+        "-g" => mode & 0o2000 != 0,
+        "-u" => mode & 0o4000 != 0,
+        "-k" => mode & 0o1000 != 0,
+        "-O" => meta.uid() == sys::euid(),
+        "-G" => meta.gid() == sys::egid(),
+        "-N" => meta.modified().ok() > meta.accessed().ok(),
+        _ => false,
+    }
+}
+
+// the string and file comparisons, the numeric ones go through `compare`
+pub fn binary(left: &str, op: &str, right: &str) -> bool {
+    let modified = |path: &str| -> Option<SystemTime> { fs::metadata(path).ok()?.modified().ok() };
+    match op {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "-nt" => match (modified(left), modified(right)) {
+            (Some(left), Some(right)) => left > right,
+            (left, right) => left.is_some() && right.is_none(),
+        },
+        "-ot" => match (modified(left), modified(right)) {
+            (Some(left), Some(right)) => left < right,
+            (left, right) => left.is_none() && right.is_some(),
+        },
+        "-ef" => match (fs::metadata(left), fs::metadata(right)) {
+            (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+pub fn compare(left: i64, op: &str, right: i64) -> bool {
+    match op {
+        "-eq" => left == right,
+        "-ne" => left != right,
+        "-lt" => left < right,
+        "-le" => left <= right,
+        "-gt" => left > right,
+        "-ge" => left >= right,
+        _ => false,
+    }
+}
@@ -0,0 +1,949 @@
+use std::cell::Cell;
+use std::io::{self, Write};
+
+use crate::complete::{Completer, Completion};
+use crate::highlight::{self, Style};
+use crate::history::History;
+use crate::shell::Shell;
+use crate::sys;
+use crate::unicode;
+
+// a key press, decoded from the bytes the terminal sends for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    // a letter pressed with Ctrl, as the lowercase letter, or Ctrl-_
+    Ctrl(char),
+    // a character typed with Alt, which sends it after an Esc
+    Alt(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    // Shift-Tab
+    BackTab,
+    // Esc on its own, not starting an escape sequence
+    Escape,
+    // the start of pasted text, which `read_paste` reads the rest of
+    Paste,
+    // the terminal changed size
+    Resize,
+    Unknown,
+}
+
+// reads a line from the terminal in raw mode, redrawing it after every key
+#[derive(Debug, Default)]
+pub struct Editor {
+    line: Vec<char>,
+    // an index into `line`, where typed characters go
+    cursor: usize,
+    prompt: String,
+    // how many terminal rows down from the prompt the cursor was left
+    row: Cell<usize>,
+    // how wide the terminal is, where the line wraps
+    columns: usize,
+    // the history entry on show, `None` for the line being typed
+    recalled: Option<usize>,
+    // the line being typed, kept while history entries are on show
+    draft: Vec<char>,
+    completer: Completer,
+    // the completions on show below the line
+    menu: Option<Menu>,
+    // the rest of the newest history entry that starts with the line, shown dimmed after it
+    suggestion: String,
+    // how each character of the line is drawn
+    styles: Vec<Style>,
+    // under `set -o vi`, whether keys are commands rather than text to insert
+    normal: bool,
+    // the vi operator waiting for the motion that says what it works on
+    pending: Option<char>,
+    // the text vi's `d`, `c`, `y` and `x` last took, which `p` puts back
+    register: Vec<char>,
+    // killed text, newest last, which Ctrl-Y yanks back
+    kill_ring: Vec<Vec<char>>,
+    // whether the last key killed text, the next kill then adds to the same entry
+    killed: bool,
+    killing: bool,
+    // where the last key yanked text to and the kill ring entry it was, for Alt-Y
+    yanked: Option<(usize, usize, usize)>,
+    // where the last key put a history entry's last word and the entry it came from, so Alt-.
+    // again can swap it for an older one
+    last_word: Option<(usize, usize, usize)>,
+    // the line as it was before each change, newest last, which undo goes back through
+    undo: Vec<Snapshot>,
+    // the changes undone since the line last changed, which redo makes again
+    redo: Vec<Snapshot>,
+    // the line as it was after the last change
+    saved: Snapshot,
+    // whether the last key typed part of a word, typing the rest is then the same change
+    typed: bool,
+    typing: bool,
+}
+
+// the line and where the cursor was in it, as undo puts it back
+#[derive(Debug, Default, Clone)]
+struct Snapshot {
+    line: Vec<char>,
+    cursor: usize,
+    recalled: Option<usize>,
+}
+
+// how many kills the kill ring keeps
+const KILL_RING_SIZE: usize = 10;
+
+// completions laid out in rows below the line, which Tab and the arrow keys move through
+#[derive(Debug)]
+struct Menu {
+    // where in the line the word being completed starts
+    start: usize,
+    candidates: Vec<String>,
+    // the completion put in the line, none until one is picked
+    selected: Option<usize>,
+    columns: usize,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the line typed after `prompt`, `None` at the end of the input and an `Interrupted`
+    // error after Ctrl-C
+    pub fn read_line(&mut self, prompt: &str, shell: &mut Shell) -> io::Result<Option<String>> {
+        let saved = sys::raw_mode(0)?;
+        // with bracketed paste on the terminal marks pasted text, so its newlines don't run it
+        self.write("\x1b[?2004h")?;
+        let line = self.edit(prompt, shell);
+        self.write("\x1b[?2004l")?;
+        sys::set_termios(0, &saved)?;
+        line
+    }
+
+    fn edit(&mut self, prompt: &str, shell: &mut Shell) -> io::Result<Option<String>> {
+        self.line.clear();
+        self.cursor = 0;
+        self.prompt = prompt.to_owned();
+        self.resize(shell);
+        self.row.set(0);
+        self.recalled = None;
+        self.suggestion.clear();
+        self.styles.clear();
+        self.normal = false;
+        self.undo.clear();
+        self.redo.clear();
+        self.saved = Snapshot::default();
+        self.redraw()?;
+        loop {
+            let Some(key) = read_key()? else {
+                return Ok(None);
+            };
+            self.killing = std::mem::take(&mut self.killed);
+            let yanked = self.yanked.take();
+            let last_word = self.last_word.take();
+            self.typing = std::mem::take(&mut self.typed);
+            let key = match key {
+                // in vi's normal mode Ctrl-R is redo
+                Key::Ctrl('r') if !(self.normal && shell.option("vi")) => {
+                    match self.search(shell)? {
+                        Some(key) => key,
+                        None => {
+                            self.refresh(shell);
+                            self.redraw()?;
+                            continue;
+                        }
+                    }
+                }
+                key => key,
+            };
+            if self.menu.is_some() {
+                if self.pick(key) {
+                    self.refresh(shell);
+                    self.redraw()?;
+                    continue;
+                }
+                // any other key puts the menu away and goes on to do what it usually does
+                self.menu = None;
+                self.redraw()?;
+            }
+            if self.normal && shell.option("vi") && self.vi_command(key, &shell.history) {
+                // the cursor stays on a character outside of insert mode
+                if self.normal && self.cursor == self.line.len() {
+                    self.cursor = unicode::prev_boundary(&self.line, self.cursor);
+                }
+                self.refresh(shell);
+                self.redraw()?;
+                continue;
+            }
+            match key {
+                Key::Enter => {
+                    self.cursor = self.line.len();
+                    self.suggestion.clear();
+                    self.redraw()?;
+                    self.write("\n")?;
+                    return Ok(Some(self.line.iter().collect()));
+                }
+                Key::Ctrl('c') => {
+                    self.cursor = self.line.len();
+                    self.suggestion.clear();
+                    self.redraw()?;
+                    self.write("^C\n")?;
+                    // as the signal would have been with the terminal in its usual mode
+                    sys::mark_caught(sys::SIGINT);
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+                Key::Ctrl('d') if self.line.is_empty() => {
+                    self.write("\n")?;
+                    return Ok(None);
+                }
+                Key::Ctrl('d') | Key::Delete => {
+                    let end = unicode::next_boundary(&self.line, self.cursor);
+                    self.line.drain(self.cursor..end);
+                }
+                Key::Backspace => {
+                    let start = unicode::prev_boundary(&self.line, self.cursor);
+                    self.line.drain(start..self.cursor);
+                    self.cursor = start;
+                }
+                // at the end of the line these take the suggestion
+                Key::Right | Key::End | Key::Ctrl('f' | 'e')
+                    if self.cursor == self.line.len() && !self.suggestion.is_empty() =>
+                {
+                    self.line.extend(self.suggestion.chars());
+                    self.cursor = self.line.len();
+                }
+                Key::Left | Key::Ctrl('b') => {
+                    self.cursor = unicode::prev_boundary(&self.line, self.cursor);
+                }
+                Key::Right | Key::Ctrl('f') => {
+                    self.cursor = unicode::next_boundary(&self.line, self.cursor);
+                }
+                Key::Up | Key::Ctrl('p') => self.recall(&shell.history, true),
+                Key::Down | Key::Ctrl('n') => self.recall(&shell.history, false),
+                // Tab
+                Key::Ctrl('i') => self.complete(shell)?,
+                Key::Home | Key::Ctrl('a') => self.cursor = 0,
+                Key::End | Key::Ctrl('e') => self.cursor = self.line.len(),
+                Key::Alt('b') => self.cursor = self.word_back(),
+                Key::Alt('f') => self.cursor = self.word_forward(),
+                // back to the last space, where Alt-B stops at punctuation as well
+                Key::Ctrl('w') => {
+                    let mut start = self.cursor;
+                    while start > 0 && self.line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                    while start > 0 && !self.line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                    self.kill(start, self.cursor);
+                }
+                Key::Ctrl('u') => self.kill(0, self.cursor),
+                Key::Ctrl('k') => self.kill(self.cursor, self.line.len()),
+                Key::Alt('d') => self.kill(self.cursor, self.word_forward()),
+                Key::Ctrl('y') => self.yank(self.kill_ring.len().checked_sub(1)),
+                // swaps what was just yanked for the kill before it
+                Key::Alt('y') => {
+                    if let Some((start, end, index)) = yanked {
+                        self.line.drain(start..end);
+                        self.cursor = start;
+                        let len = self.kill_ring.len();
+                        self.yank(Some((index + len - 1) % len));
+                    }
+                }
+                Key::Alt('.' | '_') => self.insert_last_word(&shell.history, last_word),
+                // swaps the characters either side of the cursor, or the last two at the end
+                Key::Ctrl('t') if self.cursor > 0 && self.line.len() > 1 => {
+                    if self.cursor == self.line.len() {
+                        self.cursor = unicode::prev_boundary(&self.line, self.cursor);
+                    }
+                    let start = unicode::prev_boundary(&self.line, self.cursor);
+                    let end = unicode::next_boundary(&self.line, self.cursor);
+                    if start < self.cursor {
+                        self.line[start..end].rotate_left(self.cursor - start);
+                        self.cursor = end;
+                    }
+                }
+                Key::Resize => self.resize(shell),
+                // clears the screen, the line is drawn again at the top
+                Key::Ctrl('l') => {
+                    self.write("\x1b[H\x1b[2J")?;
+                    self.row.set(0);
+                }
+                Key::Ctrl('_') => self.undo(),
+                Key::Ctrl('x') => match read_key()? {
+                    Some(Key::Ctrl('u')) => self.undo(),
+                    _ => continue,
+                },
+                Key::Alt('/') => self.redo(),
+                Key::Escape if shell.option("vi") => {
+                    self.normal = true;
+                    self.cursor = unicode::prev_boundary(&self.line, self.cursor);
+                }
+                Key::Paste => {
+                    let text = read_paste()?;
+                    self.line.splice(self.cursor..self.cursor, text.chars());
+                    self.cursor += text.chars().count();
+                }
+                Key::Char(c) => {
+                    self.line.insert(self.cursor, c);
+                    self.cursor += 1;
+                    self.typed = !c.is_whitespace();
+                }
+                _ => continue,
+            }
+            self.refresh(shell);
+            self.redraw()?;
+        }
+    }
+
+    // replaces the line with the next `older` or newer history entry, past the newest one
+    // is the line that was being typed
+    fn recall(&mut self, history: &History, older: bool) {
+        let index = self.recalled.unwrap_or(history.len());
+        let index = match older {
+            true if index > 0 => index - 1,
+            false if index < history.len() => index + 1,
+            _ => return,
+        };
+        if self.recalled.is_none() {
+            self.draft = std::mem::take(&mut self.line);
+        }
+        self.line = match history.get(index) {
+            Some(entry) => entry.chars().collect(),
+            None => std::mem::take(&mut self.draft),
+        };
+        self.recalled = (index < history.len()).then_some(index);
+        self.cursor = self.line.len();
+    }
+
+    // runs a key typed in vi's normal mode, whether it was one of its commands
+    fn vi_command(&mut self, key: Key, history: &History) -> bool {
+        let c = match key {
+            Key::Char(c) => c,
+            Key::Ctrl('r') if self.pending.is_none() => {
+                self.redo();
+                return true;
+            }
+            _ => {
+                self.pending = None;
+                return false;
+            }
+        };
+        if let Some(operator) = self.pending.take() {
+            // `cw` leaves the space after the word, as `ce` would
+            let motion = match (operator, c) {
+                ('c', 'w') => 'e',
+                _ => c,
+            };
+            let (start, end) = match self.vi_motion(motion) {
+                // doubled, as in `dd`, it works on the whole line
+                _ if c == operator => (0, self.line.len()),
+                Some(to) => (self.cursor.min(to), self.cursor.max(to)),
+                None => return true,
+            };
+            self.register = self.line[start..end].to_vec();
+            match operator {
+                'y' => self.cursor = start,
+                _ => self.kill(start, end),
+            }
+            self.normal = operator != 'c';
+            return true;
+        }
+        let len = self.line.len();
+        match c {
+            'i' => self.normal = false,
+            'a' => {
+                self.normal = false;
+                self.cursor = unicode::next_boundary(&self.line, self.cursor);
+            }
+            'A' => {
+                self.normal = false;
+                self.cursor = len;
+            }
+            'I' => {
+                self.normal = false;
+                self.cursor = 0;
+            }
+            'x' if self.cursor < len => {
+                let end = unicode::next_boundary(&self.line, self.cursor);
+                self.register = self.line.drain(self.cursor..end).collect();
+            }
+            'D' | 'C' => {
+                self.register = self.line[self.cursor..].to_vec();
+                self.kill(self.cursor, len);
+                self.normal = c == 'D';
+            }
+            'd' | 'c' | 'y' => self.pending = Some(c),
+            'p' | 'P' => {
+                let at = match c {
+                    'p' => unicode::next_boundary(&self.line, self.cursor),
+                    _ => self.cursor,
+                };
+                self.line.splice(at..at, self.register.iter().copied());
+                let end = at + self.register.len();
+                self.cursor = unicode::prev_boundary(&self.line, end).max(at);
+            }
+            'u' => self.undo(),
+            'j' => self.recall(history, false),
+            'k' => self.recall(history, true),
+            // `e` lands on the last character of the word rather than after it
+            'e' => {
+                let to = self.vi_motion(c).unwrap_or(0);
+                self.cursor = unicode::prev_boundary(&self.line, to);
+            }
+            c => {
+                if let Some(to) = self.vi_motion(c) {
+                    self.cursor = to;
+                }
+            }
+        }
+        true
+    }
+
+    // where a vi motion key moves the cursor to, `None` for keys that aren't motions
+    fn vi_motion(&self, c: char) -> Option<usize> {
+        // vi words are runs of letters, digits and `_` or runs of other non-blanks
+        let class = |c: char| match c {
+            _ if c.is_whitespace() => 0,
+            _ if c.is_alphanumeric() || c == '_' => 1,
+            _ => 2,
+        };
+        let line = &self.line;
+        let mut at = self.cursor;
+        match c {
+            'h' => at = unicode::prev_boundary(line, at),
+            'l' => at = unicode::next_boundary(line, at),
+            '0' => at = 0,
+            '$' => at = line.len(),
+            '^' => at = line.iter().position(|c| !c.is_whitespace()).unwrap_or(0),
+            'w' | 'e' => {
+                if let Some(&c) = line.get(at) {
+                    while at < line.len() && class(line[at]) == class(c) {
+                        at += 1;
+                    }
+                }
+                while c == 'w' && at < line.len() && line[at].is_whitespace() {
+                    at += 1;
+                }
+            }
+            'b' => {
+                while at > 0 && line[at - 1].is_whitespace() {
+                    at -= 1;
+                }
+                if at > 0 {
+                    let word = class(line[at - 1]);
+                    while at > 0 && class(line[at - 1]) == word {
+                        at -= 1;
+                    }
+                }
+            }
+            _ => return None,
+        }
+        Some(at)
+    }
+
+    // where the word before the cursor starts, words being runs of letters and digits
+    fn word_back(&self) -> usize {
+        let mut start = self.cursor;
+        while start > 0 && !self.line[start - 1].is_alphanumeric() {
+            start -= 1;
+        }
+        while start > 0 && self.line[start - 1].is_alphanumeric() {
+            start -= 1;
+        }
+        start
+    }
+
+    // where the word after the cursor ends
+    fn word_forward(&self) -> usize {
+        let mut end = self.cursor;
+        while end < self.line.len() && !self.line[end].is_alphanumeric() {
+            end += 1;
+        }
+        while end < self.line.len() && self.line[end].is_alphanumeric() {
+            end += 1;
+        }
+        end
+    }
+
+    // moves the text between `start` and `end`, the cursor being at one end of it, onto the
+    // kill ring, joined to the previous kill when the key before also killed
+    fn kill(&mut self, start: usize, end: usize) {
+        let text = self.line.drain(start..end).collect::<Vec<_>>();
+        if text.is_empty() {
+            return;
+        }
+        match self.kill_ring.last_mut() {
+            // killing backwards puts the text in front
+            Some(last) if self.killing && end <= self.cursor => {
+                last.splice(0..0, text);
+            }
+            Some(last) if self.killing => last.extend(text),
+            _ => {
+                self.kill_ring.push(text);
+                if self.kill_ring.len() > KILL_RING_SIZE {
+                    self.kill_ring.remove(0);
+                }
+            }
+        }
+        self.cursor = start;
+        self.killed = true;
+    }
+
+    // puts the kill ring entry at `index` in at the cursor
+    fn yank(&mut self, index: Option<usize>) {
+        let Some((index, text)) = index.and_then(|index| Some((index, self.kill_ring.get(index)?)))
+        else {
+            return;
+        };
+        let start = self.cursor;
+        self.line.splice(start..start, text.iter().copied());
+        self.cursor += text.len();
+        self.yanked = Some((start, self.cursor, index));
+    }
+
+    // puts the last word of the newest history entry in at the cursor, or after the same key
+    // replaces the word it put in with the one from the entry before
+    fn insert_last_word(&mut self, history: &History, last: Option<(usize, usize, usize)>) {
+        let before = last.map_or(history.len(), |(_, _, index)| index);
+        let found = (0..before)
+            .rev()
+            .find_map(|index| Some((index, history.last_word(index)?)));
+        let Some((index, word)) = found else {
+            // past the oldest entry the word stays, ready to cycle again
+            self.last_word = last;
+            return;
+        };
+        if let Some((start, end, _)) = last {
+            self.line.drain(start..end);
+            self.cursor = start;
+        }
+        let start = self.cursor;
+        self.line.splice(start..start, word.chars());
+        self.cursor += word.chars().count();
+        self.last_word = Some((start, self.cursor, index));
+    }
+
+    // takes the terminal's size again, setting `COLUMNS` and `LINES` to it
+    fn resize(&mut self, shell: &mut Shell) {
+        let size = sys::terminal_size(1);
+        self.columns = size.map_or(80, |(columns, _)| columns);
+        if let Some((columns, lines)) = size {
+            let _ = shell.vars.set("COLUMNS", columns.to_string());
+            let _ = shell.vars.set("LINES", lines.to_string());
+        }
+        // a terminal that rewraps what's on it at the new width puts the cursor on this row
+        let prompt = visible(&self.prompt).chain(self.line[..self.cursor].iter().copied());
+        self.row
+            .set(unicode::advance((0, 0), prompt, self.columns).0);
+    }
+
+    // puts the line back as it was before the last change
+    fn undo(&mut self) {
+        if let Some(snapshot) = self.undo.pop() {
+            let current = self.restore(snapshot);
+            self.redo.push(current);
+        }
+    }
+
+    // makes the last change undone again
+    fn redo(&mut self) {
+        if let Some(snapshot) = self.redo.pop() {
+            let current = self.restore(snapshot);
+            self.undo.push(current);
+        }
+    }
+
+    // changes the line to `snapshot`, returning the line it was
+    fn restore(&mut self, snapshot: Snapshot) -> Snapshot {
+        let current = Snapshot {
+            line: std::mem::replace(&mut self.line, snapshot.line.clone()),
+            cursor: self.cursor,
+            recalled: self.recalled,
+        };
+        self.cursor = snapshot.cursor.min(self.line.len());
+        self.recalled = snapshot.recalled;
+        self.menu = None;
+        self.saved = snapshot;
+        current
+    }
+
+    // works out the suggestion and the highlighting again after the line changed, keeping
+    // what it was before for undo
+    fn refresh(&mut self, shell: &mut Shell) {
+        if self.line != self.saved.line {
+            let snapshot = Snapshot {
+                line: self.line.clone(),
+                cursor: self.cursor,
+                recalled: self.recalled,
+            };
+            let before = std::mem::replace(&mut self.saved, snapshot);
+            // the rest of a word being typed doesn't need undoing on its own
+            if !(self.typed && self.typing) {
+                self.undo.push(before);
+            }
+            self.redo.clear();
+        }
+        self.suggest(&shell.history);
+        self.styles =
+            highlight::highlight(&self.line, |name| self.completer.is_command(shell, name));
+    }
+
+    // suggests the newest history entry that the line is the start of, while the cursor is at
+    // the end of it
+    fn suggest(&mut self, history: &History) {
+        self.suggestion.clear();
+        if self.line.is_empty() || self.cursor < self.line.len() {
+            return;
+        }
+        let line = self.line.iter().collect::<String>();
+        let entry = (0..history.len())
+            .rev()
+            .filter_map(|index| history.get(index))
+            .filter(|entry| !entry.contains('\n'))
+            .find(|entry| entry.len() > line.len() && entry.starts_with(&line));
+        if let Some(entry) = entry {
+            self.suggestion = entry[line.len()..].to_owned();
+        }
+    }
+
+    // completes the word before the cursor as far as all its completions agree, with a space
+    // after a word that's finished; when there's more than one they're shown in a menu
+    fn complete(&mut self, shell: &mut Shell) -> io::Result<()> {
+        let before = self.line[..self.cursor].iter().collect::<String>();
+        let Completion { len, candidates } = self.completer.complete(shell, &before);
+        let word = self.line[self.cursor - len..self.cursor]
+            .iter()
+            .collect::<String>();
+        let mut text = match candidates.as_slice() {
+            [] => return self.write("\x07"),
+            [only] => only.clone(),
+            [first, rest @ ..] => rest.iter().fold(first.clone(), |common, candidate| {
+                let len = (common.char_indices())
+                    .zip(candidate.chars())
+                    .find(|((_, a), b)| a != b)
+                    .map_or(common.len().min(candidate.len()), |((i, _), _)| i);
+                common[..len].to_owned()
+            }),
+        };
+        if candidates.len() == 1 {
+            // a directory is finished by its `/`
+            if !text.ends_with('/') {
+                text.push(' ');
+            }
+        } else {
+            if !text.starts_with(&word) {
+                // nothing they all share can be added
+                text = word;
+            }
+            let width = candidates.iter().map(|c| unicode::str_width(c)).max();
+            self.menu = Some(Menu {
+                start: self.cursor - len,
+                columns: (self.columns / (width.unwrap_or(0) + 2)).max(1),
+                candidates,
+                selected: None,
+            });
+        }
+        self.line
+            .splice(self.cursor - len..self.cursor, text.chars());
+        self.cursor = self.cursor - len + text.chars().count();
+        Ok(())
+    }
+
+    // moves through the menu for Tab, Shift-Tab and the arrow keys, putting the completion
+    // picked in the line, and closes it with Enter once one is; whether `key` was used
+    fn pick(&mut self, key: Key) -> bool {
+        let Some(menu) = &mut self.menu else {
+            return false;
+        };
+        let columns = menu.columns as isize;
+        let step = match key {
+            Key::Ctrl('i') | Key::Right => 1,
+            Key::BackTab | Key::Left => -1,
+            Key::Down => columns,
+            Key::Up => -columns,
+            Key::Enter if menu.selected.is_some() => {
+                self.menu = None;
+                return true;
+            }
+            _ => return false,
+        };
+        let count = menu.candidates.len() as isize;
+        let selected = match menu.selected {
+            Some(index) => (index as isize + step).rem_euclid(count),
+            None if step > 0 => 0,
+            None => count - 1,
+        } as usize;
+        menu.selected = Some(selected);
+        let candidate = &menu.candidates[selected];
+        self.line.splice(menu.start..self.cursor, candidate.chars());
+        self.cursor = menu.start + candidate.chars().count();
+        true
+    }
+
+    // searches back through the history for entries containing the text typed since Ctrl-R,
+    // Esc keeps the entry found and Ctrl-G goes back to the line from before; any other key
+    // keeps the entry and is returned for the editor to act on
+    fn search(&mut self, shell: &mut Shell) -> io::Result<Option<Key>> {
+        let (line, cursor, recalled) = (self.line.clone(), self.cursor, self.recalled);
+        self.suggestion.clear();
+        self.styles.clear();
+        let mut query = String::new();
+        let mut failed = false;
+        loop {
+            self.redraw_search(&query, failed)?;
+            let Some(key) = read_key()? else {
+                return Ok(None);
+            };
+            // where to look back from, the entry on show is still a match for a longer query
+            let before = match key {
+                Key::Char(c) => {
+                    query.push(c);
+                    self.recalled.map_or(shell.history.len(), |index| index + 1)
+                }
+                Key::Backspace => {
+                    query.pop();
+                    shell.history.len()
+                }
+                Key::Ctrl('r') => self.recalled.unwrap_or(shell.history.len()),
+                Key::Ctrl('g') => {
+                    (self.line, self.cursor, self.recalled) = (line, cursor, recalled);
+                    return Ok(None);
+                }
+                Key::Escape => return Ok(None),
+                Key::Resize => {
+                    self.resize(shell);
+                    continue;
+                }
+                key => return Ok(Some(key)),
+            };
+            let history = &shell.history;
+            let found = match query.is_empty() {
+                true => None,
+                false => history.find(&query, before),
+            };
+            failed = found.is_none() && !query.is_empty();
+            let Some(index) = found else {
+                continue;
+            };
+            let entry = history.get(index).unwrap_or_default();
+            if self.recalled.is_none() {
+                self.draft = std::mem::take(&mut self.line);
+            }
+            self.line = entry.chars().collect();
+            self.cursor = entry[..entry.find(&query).unwrap_or(0)].chars().count();
+            self.recalled = Some(index);
+        }
+    }
+
+    // shows the search query in place of the prompt, with the cursor on the match
+    fn redraw_search(&self, query: &str, failed: bool) -> io::Result<()> {
+        let failed = if failed { "failed " } else { "" };
+        self.draw(&format!("({}reverse-i-search)`{}': ", failed, query))
+    }
+
+    fn redraw(&self) -> io::Result<()> {
+        self.draw(&self.prompt)
+    }
+
+    // writes `prompt` and the line over the terminal lines it was on and puts the cursor back
+    // with the menu below it, what was below before is cleared
+    fn draw(&self, prompt: &str) -> io::Result<()> {
+        let mut out = String::new();
+        if self.row.get() > 0 {
+            out.push_str(&format!("\x1b[{}A", self.row.get()));
+        }
+        let shown = prompt.replace(['\x01', '\x02'], "");
+        out.push_str(&format!("\r{}{}", shown, self.styled()));
+        // the suggestion is only made with the cursor at the end of the line
+        if !self.suggestion.is_empty() {
+            out.push_str(&format!("\x1b[2m{}\x1b[0m", self.suggestion));
+        }
+        let start = unicode::advance((0, 0), visible(prompt), self.columns);
+        let cursor = unicode::advance(
+            start,
+            self.line[..self.cursor].iter().copied(),
+            self.columns,
+        );
+        let rest = self.line[self.cursor..].iter().copied();
+        let mut end = unicode::advance(cursor, rest.chain(self.suggestion.chars()), self.columns);
+        // text filling the last row leaves the terminal's cursor at its end until something
+        // more is written, which takes it to the next row the cursor may have to go to
+        if end.0 > 0 && end.1 == 0 {
+            out.push_str(" \r");
+        }
+        out.push_str("\x1b[J");
+        if let Some(menu) = &self.menu {
+            let width = menu.candidates.iter().map(|c| unicode::str_width(c)).max();
+            let width = width.unwrap_or(0) + 2;
+            for (i, candidate) in menu.candidates.iter().enumerate() {
+                if i % menu.columns == 0 {
+                    out.push_str("\r\n");
+                }
+                match menu.selected == Some(i) {
+                    true => out.push_str(&format!("\x1b[7m{}\x1b[0m", candidate)),
+                    false => out.push_str(candidate),
+                }
+                let padding = width - unicode::str_width(candidate);
+                out.extend(std::iter::repeat(' ').take(padding));
+            }
+            end.0 += menu.candidates.len().div_ceil(menu.columns);
+        }
+        // back up to the cursor's row and across to its column
+        if end.0 > cursor.0 {
+            out.push_str(&format!("\x1b[{}A", end.0 - cursor.0));
+        }
+        out.push('\r');
+        if cursor.1 > 0 {
+            out.push_str(&format!("\x1b[{}C", cursor.1));
+        }
+        self.row.set(cursor.0);
+        self.write(&out)
+    }
+
+    // the line coloured by its styles
+    fn styled(&self) -> String {
+        let mut out = String::new();
+        let mut current = Style::Plain;
+        for (i, c) in self.line.iter().enumerate() {
+            let style = self.styles.get(i).copied().unwrap_or(Style::Plain);
+            if style != current {
+                out.push_str(style.escape());
+                current = style;
+            }
+            out.push(*c);
+        }
+        if current != Style::Plain {
+            out.push_str(Style::Plain.escape());
+        }
+        out
+    }
+
+    fn write(&self, text: &str) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+// the characters of `prompt` that show, leaving out those between the \x01 and \x02 that
+// `\[` and `\]` in `PS1` stand for
+fn visible(prompt: &str) -> impl Iterator<Item = char> + '_ {
+    let mut hidden = false;
+    prompt.chars().filter(move |&c| {
+        match c {
+            '\x01' => hidden = true,
+            '\x02' => hidden = false,
+            _ => return !hidden,
+        }
+        false
+    })
+}
+
+// the next key pressed, or `Resize` when the terminal changes size while waiting for one
+fn read_key() -> io::Result<Option<Key>> {
+    loop {
+        if sys::take_signal(sys::SIGWINCH) {
+            return Ok(Some(Key::Resize));
+        }
+        // the signal stops the wait, the timeout covers it coming just before it started
+        match sys::readable(0, 500) {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    let Some(byte) = sys::read_byte(0)? else {
+        return Ok(None);
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x1b => read_escape()?,
+        0x01..=0x1a => Key::Ctrl((b'a' + byte - 1) as char),
+        0x1f => Key::Ctrl('_'),
+        0x00..=0x1f => Key::Unknown,
+        _ => Key::Char(read_char(byte)?),
+    };
+    Ok(Some(key))
+}
+
+// the rest of a UTF-8 character starting with `first`
+fn read_char(first: u8) -> io::Result<char> {
+    let len = match first {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    };
+    let mut bytes = vec![first];
+    while bytes.len() < len {
+        match sys::read_byte(0)? {
+            Some(byte) => bytes.push(byte),
+            None => break,
+        }
+    }
+    Ok(String::from_utf8_lossy(&bytes)
+        .chars()
+        .next()
+        .unwrap_or('\u{fffd}'))
+}
+
+// the text pasted after the terminal's start marker, up to its end marker, with the carriage
+// returns it sends for newlines made newlines
+fn read_paste() -> io::Result<String> {
+    const END: &[u8] = b"\x1b[201~";
+    let mut bytes = Vec::new();
+    while !bytes.ends_with(END) {
+        match sys::read_byte(0)? {
+            Some(byte) => bytes.push(byte),
+            None => break,
+        }
+    }
+    if bytes.ends_with(END) {
+        bytes.truncate(bytes.len() - END.len());
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    Ok(text.replace("\r\n", "\n").replace('\r', "\n"))
+}
+
+// the key for an escape sequence, `ESC [ params final` or `ESC O final`, or Esc itself when
+// nothing follows it straight away
+fn read_escape() -> io::Result<Key> {
+    if !sys::readable(0, 50)? {
+        return Ok(Key::Escape);
+    }
+    let Some(kind) = sys::read_byte(0)? else {
+        return Ok(Key::Unknown);
+    };
+    match kind {
+        b'[' | b'O' => {}
+        0x20..=0x7e => return Ok(Key::Alt(kind as char)),
+        _ => return Ok(Key::Unknown),
+    }
+    let mut params = String::new();
+    let last = loop {
+        match sys::read_byte(0)? {
+            Some(byte @ 0x40..=0x7e) => break byte,
+            Some(byte) => params.push(byte as char),
+            None => return Ok(Key::Unknown),
+        }
+    };
+    Ok(match (last, params.as_str()) {
+        (b'A', _) => Key::Up,
+        (b'B', _) => Key::Down,
+        (b'C', _) => Key::Right,
+        (b'D', _) => Key::Left,
+        (b'H', _) | (b'~', "1" | "7") => Key::Home,
+        (b'F', _) | (b'~', "4" | "8") => Key::End,
+        (b'~', "3") => Key::Delete,
+        (b'Z', _) => Key::BackTab,
+        (b'~', "200") => Key::Paste,
+        _ => Key::Unknown,
+    })
+}
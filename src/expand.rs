@@ -0,0 +1,614 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+
+use crate::arith::{self, ArithError};
+use crate::brace;
+use crate::glob::{self, Pattern};
+use crate::lexer::{self, Param, ParamOp, Replace, Word, WordPart};
+use crate::shell::Shell;
+use crate::sys;
+use crate::vars::{self, ReadonlyError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExpandError {
+    #[error("{0}: bad substitution")]
+    BadSubstitution(String),
+    #[error("{0}: {1}")]
+    Unset(String, String),
+    #[error("{0}: unbound variable")]
+    Unbound(String),
+    #[error("${0}: cannot assign in this way")]
+    CannotAssign(String),
+    #[error("{0}: substring expression < 0")]
+    NegativeLength(String),
+    #[error("{0}: ambiguous redirect")]
+    AmbiguousRedirect(String),
+    #[error(transparent)]
+    Readonly(#[from] ReadonlyError),
+    #[error(transparent)]
+    Arith(#[from] ArithError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+pub fn expand_words(shell: &mut Shell, words: &[Word]) -> Result<Vec<String>, ExpandError> {
+    let mut fields = Vec::with_capacity(words.len());
+    let ifs = shell.vars.get("IFS").unwrap_or(" \t\n").to_owned();
+    for word in words.iter().flat_map(brace::expand) {
+        let word = expand_tilde(shell, &word, false);
+        let mut out = Expansion {
+            ifs: Some(ifs.clone()),
+            ..Default::default()
+        };
+        expand_parts(shell, &word.0, &mut out, false)?;
+        for field in out.finish() {
+            let pattern = Pattern::new(&field.pattern, shell.shopt("extglob"));
+            if shell.option("noglob") || pattern.is_literal() {
+                fields.push(field.text);
+                continue;
+            }
+            let options = glob::Options {
+                globstar: shell.shopt("globstar"),
+                extglob: shell.shopt("extglob"),
+                dotglob: shell.shopt("dotglob"),
+            };
+            match glob::glob(&field.pattern, options) {
+                // a pattern that matches nothing is left as it is unless `nullglob` is on
+                paths if paths.is_empty() => {
+                    if !shell.shopt("nullglob") {
+                        fields.push(field.text);
+                    }
+                }
+                paths => fields.extend(paths),
+            }
+        }
+    }
+    Ok(fields)
+}
+
+// the expanded text, and the same text as a glob pattern where only unquoted characters
+// keep their special meaning
+#[derive(Debug, Default)]
+struct Field {
+    text: String,
+    pattern: String,
+    // whether any part of the field was quoted, which keeps it even when empty
+    quoted: bool,
+}
+
+#[derive(Debug, Default)]
+struct Expansion {
+    fields: Vec<Field>,
+    current: Field,
+    // the characters unquoted expansions are split on, `None` for a single field
+    ifs: Option<String>,
+}
+
+impl Expansion {
+    fn push(&mut self, value: &str, quoted: bool) {
+        let field = &mut self.current;
+        field.text.push_str(value);
+        match quoted {
+            true => field.pattern.push_str(&glob::escape(value)),
+            false => field.pattern.push_str(value),
+        }
+        field.quoted |= quoted;
+    }
+
+    // pushes the result of an unquoted expansion, splitting it into fields on `$IFS`
+    fn push_split(&mut self, value: &str) {
+        let ifs = match self.ifs.take() {
+            Some(ifs) if !ifs.is_empty() => ifs,
+            // an empty `$IFS` doesn't split, but `"$@"` after it still makes separate fields
+            ifs => {
+                self.ifs = ifs;
+                self.push(value, false);
+                return;
+            }
+        };
+        let is_space = |c: char| matches!(c, ' ' | '\t' | '\n') && ifs.contains(c);
+        let mut chars = value.chars().peekable();
+        let mut start = 0;
+        let mut end = 0;
+        while let Some(c) = chars.next() {
+            if !ifs.contains(c) {
+                end += c.len_utf8();
+                continue;
+            }
+            self.push(&value[start..end], false);
+            // whitespace around at most one other separator delimits a single field
+            let mut hard = !is_space(c);
+            end += c.len_utf8();
+            while let Some(&c) = chars.peek() {
+                if is_space(c) || (!hard && ifs.contains(c)) {
+                    hard |= !is_space(c);
+                    end += c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            start = end;
+            let field = std::mem::take(&mut self.current);
+            if hard || !field.text.is_empty() || field.quoted {
+                self.fields.push(field);
+            }
+        }
+        self.push(&value[start..end], false);
+        self.ifs = Some(ifs);
+    }
+
+    // pushes each value as a field of its own, the first and last joining the text around
+    // them, or all of them separated by spaces when there is only one field
+    fn push_fields(&mut self, values: &[String]) {
+        if self.ifs.is_none() {
+            self.push(&values.join(" "), true);
+            return;
+        }
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                let field = std::mem::take(&mut self.current);
+                self.fields.push(field);
+            }
+            self.push(value, true);
+        }
+    }
+
+    // the fields, without unquoted ones that expanded to nothing
+    fn finish(mut self) -> Vec<Field> {
+        if !self.current.text.is_empty() || self.current.quoted {
+            self.fields.push(self.current);
+        }
+        self.fields
+    }
+}
+
+pub fn expand_word(shell: &mut Shell, word: &Word) -> Result<String, ExpandError> {
+    let mut out = Expansion::default();
+    expand_parts(shell, &word.0, &mut out, false)?;
+    Ok(out.current.text)
+}
+
+// the value of an arithmetic expression, an empty one is 1 so a missing `for ((;;))`
+// condition loops forever
+pub fn eval_arith(shell: &mut Shell, expr: &Word) -> Result<i64, ExpandError> {
+    if expr.0.is_empty() {
+        return Ok(1);
+    }
+    let expr = expand_word(shell, expr)?;
+    Ok(arith::eval(shell, &expr)?)
+}
+
+// replaces `~`, `~user`, `~+` and `~-` at the start of `word` (and after each `:` in an
+// assignment) with the directory, quoted so it is not expanded any further
+pub fn expand_tilde(shell: &Shell, word: &Word, assignment: bool) -> Word {
+    let mut parts = Vec::with_capacity(word.0.len());
+    for (i, part) in word.0.iter().enumerate() {
+        let WordPart::Literal(text) = part else {
+            parts.push(part.clone());
+            continue;
+        };
+        let last = i + 1 == word.0.len();
+        let mut literal = String::new();
+        let mut rest = text.as_str();
+        let mut at_start = i == 0;
+        loop {
+            if at_start && rest.starts_with('~') {
+                let end = rest[1..].find(|c| c == '/' || (assignment && c == ':'));
+                let user = &rest[1..end.map_or(rest.len(), |end| end + 1)];
+                // a prefix running into a quoted part is left alone
+                let dir = match end.is_some() || last {
+                    true => tilde_dir(shell, user),
+                    false => None,
+                };
+                if let Some(dir) = dir {
+                    if !literal.is_empty() {
+                        parts.push(WordPart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(WordPart::Quoted(dir));
+                    rest = &rest[1 + user.len()..];
+                }
+            }
+            match rest.find(':').filter(|_| assignment) {
+                Some(pos) => {
+                    literal.push_str(&rest[..=pos]);
+                    rest = &rest[pos + 1..];
+                    at_start = true;
+                }
+                None => {
+                    literal.push_str(rest);
+                    break;
+                }
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(WordPart::Literal(literal));
+        }
+    }
+    Word(parts)
+}
+
+fn tilde_dir(shell: &Shell, user: &str) -> Option<String> {
+    match user {
+        "" => shell.vars.get("HOME").map(str::to_owned),
+        "+" => std::env::current_dir()
+            .ok()
+            .map(|dir| dir.to_string_lossy().into_owned()),
+        "-" => shell.vars.get("OLDPWD").map(str::to_owned),
+        _ => sys::home_dir(user),
+    }
+}
+
+// expands `word` into a glob pattern where only unquoted text keeps its special meaning
+pub fn expand_pattern(shell: &mut Shell, word: &Word) -> Result<String, ExpandError> {
+    let mut out = Expansion::default();
+    expand_parts(shell, &word.0, &mut out, false)?;
+    Ok(out.current.pattern)
+}
+
+// expands `word` into an extended regular expression where quoted text only matches itself
+pub fn expand_regex(shell: &mut Shell, word: &Word) -> Result<String, ExpandError> {
+    let mut out = String::new();
+    for part in &word.0 {
+        let value = expand_word(shell, &Word(vec![part.clone()]))?;
+        match part {
+            WordPart::Quoted(_) | WordPart::DoubleQuoted(_) => {
+                for c in value.chars() {
+                    if "\\.[]()|*+?{}^$".contains(c) {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+            }
+            _ => out.push_str(&value),
+        }
+    }
+    Ok(out)
+}
+
+// `quoted` is whether `parts` are inside double quotes
+fn expand_parts(
+    shell: &mut Shell,
+    parts: &[WordPart],
+    out: &mut Expansion,
+    quoted: bool,
+) -> Result<(), ExpandError> {
+    for part in parts {
+        match part {
+            WordPart::Literal(v) => out.push(v, quoted),
+            WordPart::Quoted(v) => out.push(v, true),
+            WordPart::DoubleQuoted(parts) => {
+                // a lone `"$@"` with nothing to expand to leaves no field behind
+                if let [WordPart::Param(param)] = parts.as_slice() {
+                    if let Some(values) = all_elements(shell, param)? {
+                        if !values.is_empty() {
+                            out.push("", true);
+                            out.push_fields(&values);
+                        }
+                        continue;
+                    }
+                }
+                out.push("", true);
+                expand_parts(shell, parts, out, true)?
+            }
+            WordPart::Param(param) if quoted => match all_elements(shell, param)? {
+                Some(values) => out.push_fields(&values),
+                None => {
+                    let value = expand_param(shell, param)?;
+                    out.push(&value, true);
+                }
+            },
+            WordPart::Param(param) => {
+                let value = expand_param(shell, param)?;
+                push_expansion(out, &value, quoted);
+            }
+            WordPart::Command(cmd) => {
+                let value = command_subst(shell, cmd)?;
+                push_expansion(out, &value, quoted);
+            }
+            WordPart::Arith(expr) => {
+                let expr = expand_word(shell, expr)?;
+                let value = arith::eval(shell, &expr)?;
+                push_expansion(out, &value.to_string(), quoted);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn push_expansion(out: &mut Expansion, value: &str, quoted: bool) {
+    match quoted {
+        true => out.push(value, true),
+        false => out.push_split(value),
+    }
+}
+
+// the words `"$@"`, `"${@:offset:length}"` and `"${name[@]}"` expand to, each one becoming
+// a field of its own
+fn all_elements(shell: &mut Shell, param: &Param) -> Result<Option<Vec<String>>, ExpandError> {
+    match &param.op {
+        ParamOp::None => {}
+        ParamOp::Substring(offset, length) if param.name == "@" => {
+            return slice_args(shell, param, offset, length.as_ref()).map(Some);
+        }
+        _ => return Ok(None),
+    }
+    Ok(match subscript(&param.name) {
+        Some((name, "@")) => Some(
+            shell
+                .vars
+                .elements(name)
+                .into_iter()
+                .map(str::to_owned)
+                .collect(),
+        ),
+        _ if param.name == "@" => Some(shell.args.clone()),
+        _ => None,
+    })
+}
+
+fn lookup(shell: &mut Shell, name: &str) -> Result<Option<String>, ExpandError> {
+    if let Some((name, index)) = subscript(name) {
+        if let "@" | "*" = index {
+            return Ok(Some(shell.vars.elements(name).join(" ")).filter(|v| !v.is_empty()));
+        }
+        let index = eval_arith(shell, &lexer::unsplit(index))?;
+        return Ok(shell.vars.element(name, index).map(str::to_owned));
+    }
+    Ok(match name {
+        "?" => Some(shell.last_status.to_string()),
+        "$" => Some(shell.pid.to_string()),
+        "!" => shell.last_background.map(|pid| pid.to_string()),
+        "0" => Some(shell.name.clone()),
+        "#" => Some(shell.args.len().to_string()),
+        "@" | "*" => Some(join_args(shell, name, &shell.args)),
+        _ if name.bytes().all(|c| c.is_ascii_digit()) => {
+            let index = name.parse::<usize>().ok().and_then(|i| i.checked_sub(1));
+            index.and_then(|i| shell.args.get(i)).cloned()
+        }
+        _ => shell.vars.get(name).map(str::to_owned),
+    })
+}
+
+// `$@` joined with spaces, or `$*` joined with the first character of `$IFS`
+fn join_args(shell: &Shell, name: &str, args: &[String]) -> String {
+    match name {
+        "*" => {
+            let ifs = shell.vars.get("IFS").unwrap_or(" ");
+            args.join(&ifs.chars().next().map(String::from).unwrap_or_default())
+        }
+        _ => args.join(" "),
+    }
+}
+
+// `name[index]` split into the name and the index
+fn subscript(name: &str) -> Option<(&str, &str)> {
+    name.strip_suffix(']')?.split_once('[')
+}
+
+fn expand_param(shell: &mut Shell, param: &Param) -> Result<String, ExpandError> {
+    let value = lookup(shell, &param.name)?;
+    // under `set -u` only the operators that handle an unset value may see one, `$@` and
+    // `${name[@]}` are never unset
+    let handled = matches!(
+        param.op,
+        ParamOp::Default(..) | ParamOp::Assign(..) | ParamOp::Alternate(..) | ParamOp::Error(..)
+    );
+    let all = matches!(param.name.as_str(), "@" | "*")
+        || subscript(&param.name).is_some_and(|(_, index)| matches!(index, "@" | "*"));
+    if value.is_none() && !handled && !all && shell.option("nounset") {
+        let name = subscript(&param.name).map_or(param.name.as_str(), |(name, _)| name);
+        return Err(ExpandError::Unbound(name.to_owned()));
+    }
+    let is_unset = |colon: bool| match &value {
+        Some(v) => colon && v.is_empty(),
+        None => true,
+    };
+    match &param.op {
+        ParamOp::None => Ok(value.unwrap_or_default()),
+        ParamOp::Default(colon, word) => match is_unset(*colon) {
+            true => expand_word(shell, word),
+            false => Ok(value.unwrap_or_default()),
+        },
+        ParamOp::Assign(colon, word) => match is_unset(*colon) {
+            true => {
+                if !vars::is_name(&param.name) {
+                    return Err(ExpandError::CannotAssign(param.name.clone()));
+                }
+                let value = expand_word(shell, word)?;
+                shell.vars.set(&param.name, value.clone())?;
+                Ok(value)
+            }
+            false => Ok(value.unwrap_or_default()),
+        },
+        ParamOp::Alternate(colon, word) => match is_unset(*colon) {
+            true => Ok(String::new()),
+            false => expand_word(shell, word),
+        },
+        ParamOp::Error(colon, word) => match is_unset(*colon) {
+            true => {
+                let mut msg = expand_word(shell, word)?;
+                if msg.is_empty() {
+                    msg = match colon {
+                        true => "parameter null or not set",
+                        false => "parameter not set",
+                    }
+                    .to_owned();
+                }
+                Err(ExpandError::Unset(param.name.clone(), msg))
+            }
+            false => Ok(value.unwrap_or_default()),
+        },
+        ParamOp::Length => match subscript(&param.name) {
+            Some((name, "@" | "*")) => Ok(shell.vars.elements(name).len().to_string()),
+            None if matches!(param.name.as_str(), "@" | "*") => Ok(shell.args.len().to_string()),
+            _ => Ok(value.unwrap_or_default().chars().count().to_string()),
+        },
+        ParamOp::Substring(offset, length) if matches!(param.name.as_str(), "@" | "*") => {
+            let args = slice_args(shell, param, offset, length.as_ref())?;
+            Ok(join_args(shell, &param.name, &args))
+        }
+        ParamOp::Substring(offset, length) => {
+            let value = value.unwrap_or_default();
+            let offset = expand_number(shell, offset)?;
+            let length = match length {
+                Some(length) => Some(expand_number(shell, length)?),
+                None => None,
+            };
+            substring(&value, offset, length)
+                .ok_or_else(|| ExpandError::NegativeLength(param.to_string()))
+        }
+        ParamOp::RemovePrefix(longest, word) => {
+            let value = value.unwrap_or_default();
+            let pattern = Pattern::new(&expand_pattern(shell, word)?, shell.shopt("extglob"));
+            let chars = value.chars().collect::<Vec<_>>();
+            Ok(match pattern.match_prefix(&chars, *longest) {
+                Some(end) => chars[end..].iter().collect(),
+                None => value,
+            })
+        }
+        ParamOp::RemoveSuffix(longest, word) => {
+            let value = value.unwrap_or_default();
+            let pattern = Pattern::new(&expand_pattern(shell, word)?, shell.shopt("extglob"));
+            let chars = value.chars().collect::<Vec<_>>();
+            Ok(match pattern.match_suffix(&chars, *longest) {
+                Some(start) => chars[..start].iter().collect(),
+                None => value,
+            })
+        }
+        ParamOp::Replace(mode, pattern, repl) => {
+            let value = value.unwrap_or_default();
+            let pattern = Pattern::new(&expand_pattern(shell, pattern)?, shell.shopt("extglob"));
+            let repl = expand_word(shell, repl)?;
+            Ok(replace(&value, &pattern, &repl, *mode))
+        }
+        ParamOp::Bad(_) => Err(ExpandError::BadSubstitution(param.to_string())),
+    }
+}
+
+// a substring's offset or length, which is an arithmetic expression
+fn expand_number(shell: &mut Shell, word: &Word) -> Result<i64, ExpandError> {
+    let expr = expand_word(shell, word)?;
+    Ok(arith::eval(shell, &expr)?)
+}
+
+// the positional parameters `${@:offset:length}` picks out, where `$0` comes first
+fn slice_args(
+    shell: &mut Shell,
+    param: &Param,
+    offset: &Word,
+    length: Option<&Word>,
+) -> Result<Vec<String>, ExpandError> {
+    let offset = expand_number(shell, offset)?;
+    let length = match length {
+        Some(length) => Some(expand_number(shell, length)?),
+        None => None,
+    };
+    let len = shell.args.len() as i64 + 1;
+    let start = if offset < 0 { len + offset } else { offset };
+    let end = match length {
+        Some(length) if length < 0 => {
+            return Err(ExpandError::NegativeLength(param.to_string()));
+        }
+        Some(length) => start.saturating_add(length).min(len),
+        None => len,
+    };
+    if start < 0 || start >= len {
+        return Ok(Vec::new());
+    }
+    Ok(std::iter::once(&shell.name)
+        .chain(&shell.args)
+        .skip(start as usize)
+        .take((end - start) as usize)
+        .cloned()
+        .collect())
+}
+
+// `None` when a negative length ends before `offset`
+fn substring(value: &str, offset: i64, length: Option<i64>) -> Option<String> {
+    let len = value.chars().count() as i64;
+    let start = if offset < 0 { len + offset } else { offset };
+    if start < 0 || start > len {
+        return Some(String::new());
+    }
+    let end = match length {
+        Some(length) if length < 0 => len + length,
+        Some(length) => (start + length).min(len),
+        None => len,
+    };
+    if end < start {
+        return None;
+    }
+    Some(
+        value
+            .chars()
+            .skip(start as usize)
+            .take((end - start) as usize)
+            .collect(),
+    )
+}
+
+fn replace(value: &str, pattern: &Pattern, repl: &str, mode: Replace) -> String {
+    let chars = value.chars().collect::<Vec<_>>();
+    match mode {
+        Replace::Prefix => match pattern.match_prefix(&chars, true) {
+            Some(end) => format!("{}{}", repl, chars[end..].iter().collect::<String>()),
+            None => value.to_owned(),
+        },
+        Replace::Suffix => match pattern.match_suffix(&chars, true) {
+            Some(start) => format!("{}{}", chars[..start].iter().collect::<String>(), repl),
+            None => value.to_owned(),
+        },
+        Replace::First | Replace::All => {
+            let mut out = String::with_capacity(value.len());
+            let mut index = 0;
+            let mut replaced = false;
+            while index < chars.len() {
+                if !replaced || mode == Replace::All {
+                    match pattern.match_prefix(&chars[index..], true) {
+                        Some(len) if len > 0 => {
+                            out.push_str(repl);
+                            index += len;
+                            replaced = true;
+                            continue;
+                        }
+                        _ => {}
+                    }
+                }
+                out.push(chars[index]);
+                index += 1;
+            }
+            out
+        }
+    }
+}
+
+// runs `cmd` in a forked copy of the shell and returns what it wrote to stdout
+fn command_subst(shell: &mut Shell, cmd: &str) -> Result<String, ExpandError> {
+    let (read, write) = sys::pipe()?;
+    let pid = sys::fork()?;
+    if pid == 0 {
+        drop(read);
+        let status = match sys::dup2(write.as_raw_fd(), 1) {
+            Ok(()) => {
+                drop(write);
+                shell.subshell();
+                crate::run_line(shell, cmd).unwrap_or(1)
+            }
+            Err(_) => 1,
+        };
+        sys::exit_child(status);
+    }
+    drop(write);
+    let mut out = Vec::new();
+    File::from(read).read_to_end(&mut out)?;
+    let status = sys::waitpid(pid, 0)?.code();
+    shell.last_status = status;
+    shell.subst_status = Some(status);
+    let mut out = String::from_utf8_lossy(&out).into_owned();
+    while out.ends_with('\n') {
+        out.pop();
+    }
+    Ok(out)
+}
@@ -0,0 +1,402 @@
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    // `?`
+    Any,
+    // `*`
+    Star,
+    // `[...]`
+    Class(bool, Vec<ClassItem>),
+    // `?(a|b)` and the other `extglob` patterns
+    Ext(ExtKind, Vec<Vec<Token>>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExtKind {
+    // `?(...)`
+    ZeroOrOne,
+    // `*(...)`
+    ZeroOrMore,
+    // `+(...)`
+    OneOrMore,
+    // `@(...)`
+    One,
+    // `!(...)`
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Named(String),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Char(v) => *v == c,
+            Self::Range(start, end) => (*start..=*end).contains(&c),
+            Self::Named(name) => match name.as_str() {
+                "alnum" => c.is_alphanumeric(),
+                "alpha" => c.is_alphabetic(),
+                "blank" => c == ' ' || c == '\t',
+                "cntrl" => c.is_control(),
+                "digit" => c.is_ascii_digit(),
+                "graph" => c.is_ascii_graphic(),
+                "lower" => c.is_lowercase(),
+                "print" => c.is_ascii_graphic() || c == ' ',
+                "punct" => c.is_ascii_punctuation(),
+                "space" => c.is_whitespace(),
+                "upper" => c.is_uppercase(),
+                "xdigit" => c.is_ascii_hexdigit(),
+                _ => false,
+            },
+        }
+    }
+}
+
+// a shell pattern, characters escaped with `\` match literally
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+}
+
+impl Pattern {
+    // `extglob` enables the `?(...)`, `*(...)`, `+(...)`, `@(...)` and `!(...)` forms
+    pub fn new(pattern: &str, extglob: bool) -> Self {
+        let chars = pattern.chars().collect::<Vec<_>>();
+        Self {
+            tokens: parse(&chars, extglob),
+        }
+    }
+
+    // whether the pattern has no wildcards and only matches one string
+    pub fn is_literal(&self) -> bool {
+        self.tokens
+            .iter()
+            .all(|token| matches!(token, Token::Char(_)))
+    }
+
+    // the one string a pattern without wildcards matches
+    fn literal(&self) -> Option<String> {
+        self.tokens
+            .iter()
+            .map(|token| match token {
+                Token::Char(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        self.matches_chars(&text.chars().collect::<Vec<_>>())
+    }
+
+    fn matches_chars(&self, text: &[char]) -> bool {
+        match_tokens(&self.tokens, text)
+    }
+
+    // the length in chars of the shortest or longest prefix of `text` that matches
+    pub fn match_prefix(&self, text: &[char], longest: bool) -> Option<usize> {
+        if longest {
+            (0..=text.len())
+                .rev()
+                .find(|i| self.matches_chars(&text[..*i]))
+        } else {
+            (0..=text.len()).find(|i| self.matches_chars(&text[..*i]))
+        }
+    }
+
+    // the start in chars of the shortest or longest suffix of `text` that matches
+    pub fn match_suffix(&self, text: &[char], longest: bool) -> Option<usize> {
+        if longest {
+            (0..=text.len()).find(|i| self.matches_chars(&text[*i..]))
+        } else {
+            (0..=text.len())
+                .rev()
+                .find(|i| self.matches_chars(&text[*i..]))
+        }
+    }
+}
+
+fn parse(chars: &[char], extglob: bool) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        index += 1;
+        let kind = match c {
+            '?' => Some(ExtKind::ZeroOrOne),
+            '*' => Some(ExtKind::ZeroOrMore),
+            '+' => Some(ExtKind::OneOrMore),
+            '@' => Some(ExtKind::One),
+            '!' => Some(ExtKind::Not),
+            _ => None,
+        };
+        if let Some(kind) = kind.filter(|_| extglob && chars.get(index) == Some(&'(')) {
+            if let Some((alts, len)) = parse_ext(&chars[index + 1..]) {
+                tokens.push(Token::Ext(kind, alts));
+                index += len + 1;
+                continue;
+            }
+        }
+        match c {
+            '\\' => match chars.get(index) {
+                Some(v) => {
+                    tokens.push(Token::Char(*v));
+                    index += 1;
+                }
+                None => tokens.push(Token::Char(c)),
+            },
+            '?' => tokens.push(Token::Any),
+            '*' => {
+                if tokens.last() != Some(&Token::Star) {
+                    tokens.push(Token::Star);
+                }
+            }
+            '[' => match parse_class(&chars[index..]) {
+                Some((token, len)) => {
+                    tokens.push(token);
+                    index += len;
+                }
+                None => tokens.push(Token::Char(c)),
+            },
+            _ => tokens.push(Token::Char(c)),
+        }
+    }
+    tokens
+}
+
+// `chars` starts after the `(`, returns the `|` separated alternatives and how many chars
+// they used up to and including the `)`
+fn parse_ext(chars: &[char]) -> Option<(Vec<Vec<Token>>, usize)> {
+    let mut alts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index] {
+            '\\' => index += 1,
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' => {
+                alts.push(parse(&chars[start..index], true));
+                return Some((alts, index + 1));
+            }
+            '|' if depth == 0 => {
+                alts.push(parse(&chars[start..index], true));
+                start = index + 1;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    None
+}
+
+// `chars` starts after the `[`, returns the class and how many chars it used
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut index = 0;
+    let negated = matches!(chars.first(), Some('!' | '^'));
+    if negated {
+        index += 1;
+    }
+    let mut items = Vec::new();
+    let mut first = true;
+    loop {
+        let c = *chars.get(index)?;
+        index += 1;
+        match c {
+            ']' if !first => break,
+            '[' if chars.get(index) == Some(&':') => {
+                let rest = &chars[index + 1..];
+                let end = rest.windows(2).position(|w| w == [':', ']'])?;
+                items.push(ClassItem::Named(rest[..end].iter().collect()));
+                index += end + 3;
+            }
+            _ => {
+                let c = if c == '\\' {
+                    index += 1;
+                    *chars.get(index - 1)?
+                } else {
+                    c
+                };
+                if chars.get(index) == Some(&'-') && chars.get(index + 1).is_some_and(|v| *v != ']')
+                {
+                    items.push(ClassItem::Range(c, chars[index + 1]));
+                    index += 2;
+                } else {
+                    items.push(ClassItem::Char(c));
+                }
+            }
+        }
+        first = false;
+    }
+    Some((Token::Class(negated, items), index))
+}
+
+fn match_tokens(tokens: &[Token], text: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return text.is_empty();
+    };
+    match token {
+        Token::Star => (0..=text.len()).any(|i| match_tokens(rest, &text[i..])),
+        Token::Any => !text.is_empty() && match_tokens(rest, &text[1..]),
+        Token::Char(c) => text.first() == Some(c) && match_tokens(rest, &text[1..]),
+        Token::Class(negated, items) => match text.first() {
+            Some(c) => {
+                items.iter().any(|item| item.matches(*c)) != *negated
+                    && match_tokens(rest, &text[1..])
+            }
+            None => false,
+        },
+        Token::Ext(kind, alts) => {
+            let alt_matches = |text: &[char]| alts.iter().any(|alt| match_tokens(alt, text));
+            let split = |i: usize| (&text[..i], &text[i..]);
+            match kind {
+                ExtKind::ZeroOrOne => {
+                    match_tokens(rest, text)
+                        || (0..=text.len()).any(|i| {
+                            let (head, tail) = split(i);
+                            alt_matches(head) && match_tokens(rest, tail)
+                        })
+                }
+                ExtKind::One => (0..=text.len()).any(|i| {
+                    let (head, tail) = split(i);
+                    alt_matches(head) && match_tokens(rest, tail)
+                }),
+                ExtKind::Not => (0..=text.len()).any(|i| {
+                    let (head, tail) = split(i);
+                    !alt_matches(head) && match_tokens(rest, tail)
+                }),
+                ExtKind::ZeroOrMore => match_repeat(alts, rest, text),
+                ExtKind::OneOrMore => (0..=text.len()).any(|i| {
+                    let (head, tail) = split(i);
+                    alt_matches(head) && match_repeat(alts, rest, tail)
+                }),
+            }
+        }
+    }
+}
+
+// any number of non-empty matches of `alts` followed by `rest`
+fn match_repeat(alts: &[Vec<Token>], rest: &[Token], text: &[char]) -> bool {
+    match_tokens(rest, text)
+        || (1..=text.len()).any(|i| {
+            alts.iter().any(|alt| match_tokens(alt, &text[..i]))
+                && match_repeat(alts, rest, &text[i..])
+        })
+}
+
+// escapes `value` so that it only matches itself
+pub fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '*' | '?' | '[' | ']' | '(') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// the `shopt` options that change how paths are matched
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Options {
+    // `**` matches any number of directories
+    pub globstar: bool,
+    pub extglob: bool,
+    // names starting with a `.` match without the pattern starting with one
+    pub dotglob: bool,
+}
+
+// the paths matching `pattern`, sorted, with each `/` separated component matched against
+// the names in its directory
+pub fn glob(pattern: &str, options: Options) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_owned()], rest),
+        None => (vec![String::new()], pattern),
+    };
+    let segments = rest.split('/').collect::<Vec<_>>();
+    for (i, segment) in segments.iter().enumerate() {
+        let last = i + 1 == segments.len();
+        let sep = if last { "" } else { "/" };
+        let pattern = Pattern::new(segment, options.extglob);
+        let mut next = Vec::new();
+        for path in paths {
+            if let Some(name) = pattern.literal() {
+                next.push(format!("{}{}{}", path, name, sep));
+            } else if options.globstar && *segment == "**" {
+                if !last {
+                    next.push(path.clone());
+                }
+                let mut visited = HashSet::new();
+                if let Ok(meta) = fs::metadata(if path.is_empty() { "." } else { &path }) {
+                    visited.insert((meta.dev(), meta.ino()));
+                }
+                walk(&path, last, options.dotglob, &mut visited, &mut next);
+            } else {
+                for name in read_dir(&path, &pattern, last, options.dotglob) {
+                    next.push(format!("{}{}{}", path, name, sep));
+                }
+            }
+        }
+        paths = next;
+    }
+    paths.retain(|path| fs::symlink_metadata(path).is_ok());
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+// the names in the directory `path` that `pattern` matches, only directories unless `last`
+fn read_dir(path: &str, pattern: &Pattern, last: bool, dotglob: bool) -> Vec<String> {
+    let dir = if path.is_empty() { "." } else { path };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| last || entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        // hidden files only match a pattern that starts with a `.`, unless `dotglob` is on
+        .filter(|name| {
+            dotglob || !name.starts_with('.') || pattern.tokens.first() == Some(&Token::Char('.'))
+        })
+        .filter(|name| pattern.matches(name))
+        .collect()
+}
+
+// adds every directory below `path` to `out`, and every file too if `last`; directories
+// reached twice through symlinks are only walked once
+fn walk(
+    path: &str,
+    last: bool,
+    dotglob: bool,
+    visited: &mut HashSet<(u64, u64)>,
+    out: &mut Vec<String>,
+) {
+    let pattern = Pattern::new("*", false);
+    for name in read_dir(path, &pattern, true, dotglob) {
+        let full = format!("{}{}", path, name);
+        if last {
+            out.push(full.clone());
+        }
+        let new_dir = match fs::metadata(&full) {
+            Ok(meta) => meta.is_dir() && visited.insert((meta.dev(), meta.ino())),
+            Err(_) => false,
+        };
+        if new_dir {
+            let full = format!("{}/", full);
+            if !last {
+                out.push(full.clone());
+            }
+            walk(&full, last, dotglob, visited, out);
+        }
+    }
+}
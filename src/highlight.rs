@@ -0,0 +1,118 @@
+use crate::complete;
+
+// the words the parser treats specially at the start of a command
+const KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "while", "until", "for", "in", "do", "done", "case",
+    "esac", "function", "{", "}", "!", "[[", "]]",
+];
+
+// how a character of the command line is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    // a command name that was found
+    Command,
+    // a command name that wasn't
+    Missing,
+    // in quotes
+    Quoted,
+    Operator,
+}
+
+impl Style {
+    // the escape sequence that starts drawing in this style
+    pub fn escape(self) -> &'static str {
+        match self {
+            Self::Plain => "\x1b[0m",
+            Self::Command => "\x1b[32m",
+            Self::Missing => "\x1b[31m",
+            Self::Quoted => "\x1b[33m",
+            Self::Operator => "\x1b[36m",
+        }
+    }
+}
+
+// the style of each character in `line`, `is_command` tells whether a command name is found
+pub fn highlight(line: &[char], mut is_command: impl FnMut(&str) -> bool) -> Vec<Style> {
+    let mut styles = vec![Style::Plain; line.len()];
+    // whether the next word is a command name
+    let mut command_next = true;
+    let mut i = 0;
+    while i < line.len() {
+        let c = line[i];
+        if c == ' ' || c == '\t' {
+            i += 1;
+        } else if c == '\n' {
+            // a pasted line starts another command
+            command_next = true;
+            i += 1;
+        } else if c == '#' {
+            // a comment runs to the end of the line
+            while i < line.len() && line[i] != '\n' {
+                i += 1;
+            }
+        } else if "|&;<>()".contains(c) {
+            let start = i;
+            while i < line.len() && "|&;<>()".contains(line[i]) {
+                i += 1;
+            }
+            styles[start..i].fill(Style::Operator);
+            // after a redirection comes its file
+            command_next = !line[start..i].iter().all(|c| *c == '<' || *c == '>');
+        } else {
+            let start = i;
+            let mut text = String::new();
+            let mut quote = None;
+            while i < line.len() {
+                let c = line[i];
+                match quote {
+                    Some(q) => {
+                        styles[i] = Style::Quoted;
+                        if c == q {
+                            quote = None;
+                        } else {
+                            text.push(c);
+                        }
+                    }
+                    None if c.is_whitespace() || "|&;<>()".contains(c) => break,
+                    None if c == '\'' || c == '"' => {
+                        styles[i] = Style::Quoted;
+                        quote = Some(c);
+                    }
+                    None if c == '\\' => {
+                        i += 1;
+                        text.extend(line.get(i));
+                    }
+                    None => text.push(c),
+                }
+                i += 1;
+            }
+            // a trailing backslash takes `i` past the end
+            let end = i.min(line.len());
+            let word = &line[start..end];
+            // assignments before the command don't take its place
+            let assignment = word.iter().position(|c| *c == '=').is_some_and(|eq| {
+                eq > 0
+                    && word[..eq]
+                        .iter()
+                        .all(|c| c.is_ascii_alphanumeric() || *c == '_')
+            });
+            if !command_next || assignment {
+                continue;
+            }
+            let found = KEYWORDS.contains(&text.as_str()) || is_command(&text);
+            let style = if found {
+                Style::Command
+            } else {
+                Style::Missing
+            };
+            for at in &mut styles[start..end] {
+                if *at == Style::Plain {
+                    *at = style;
+                }
+            }
+            command_next = complete::COMMAND_KEYWORDS.contains(&text.as_str());
+        }
+    }
+    styles
+}
@@ -0,0 +1,318 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+use std::time::SystemTime;
+
+use crate::sys;
+
+// the commands typed into an interactive shell, oldest first
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<Entry>,
+    // how many entries were forgotten, the first entry kept is numbered one more
+    base: usize,
+    // how much of the history file has been read, what other shells add after it is new
+    synced: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    line: String,
+    // when it was typed in seconds since the epoch, unknown for lines from a history file
+    // without timestamps
+    time: Option<u64>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0}: event not found")]
+pub struct EventNotFound(String);
+
+impl History {
+    // whether `line` was worth keeping
+    pub fn push(&mut self, line: &str) -> bool {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
+        self.add(line, now.ok().map(|now| now.as_secs()))
+    }
+
+    fn add(&mut self, line: &str, time: Option<u64>) -> bool {
+        if line.trim().is_empty() {
+            return false;
+        }
+        let line = line.to_owned();
+        self.entries.push(Entry { line, time });
+        true
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(|entry| entry.line.as_str())
+    }
+
+    pub fn time(&self, index: usize) -> Option<u64> {
+        self.entries.get(index).and_then(|entry| entry.time)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // the number the entry at `index` is listed under
+    pub fn number(&self, index: usize) -> usize {
+        self.base + index + 1
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.base = 0;
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.entries.remove(index);
+    }
+
+    // removes every entry that's the same as `line`
+    pub fn erase(&mut self, line: &str) {
+        self.entries.retain(|entry| entry.line != line);
+    }
+
+    // the newest entry before `before` that contains `text`
+    pub fn find(&self, text: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.line.contains(text))
+    }
+
+    // the entry before `end` that `spec` picks, by its number, as an offset back from `end`
+    // when negative, or by how it starts; numbers past either end give the entry at that end
+    pub fn lookup(&self, spec: &str, end: usize) -> Option<usize> {
+        let end = end.min(self.entries.len());
+        let index = match spec.parse::<i64>() {
+            Ok(back @ ..=-1) => end.saturating_sub(back.unsigned_abs() as usize),
+            Ok(number) => (number as usize)
+                .saturating_sub(self.number(0))
+                .min(end.saturating_sub(1)),
+            Err(_) => self.entries[..end]
+                .iter()
+                .rposition(|entry| entry.line.starts_with(spec))?,
+        };
+        (index < end).then_some(index)
+    }
+
+    // forgets all but the newest `size` entries
+    pub fn limit(&mut self, size: usize) {
+        let extra = self.entries.len().saturating_sub(size);
+        self.entries.drain(..extra);
+        self.base += extra;
+    }
+
+    // `line` with `!!`, `!n`, `!-n`, `!$` and `!prefix` replaced by the entries they refer to,
+    // apart from in single quotes or after a backslash
+    pub fn expand(&self, line: &str) -> Result<String, EventNotFound> {
+        let mut out = String::new();
+        let (mut single, mut double) = (false, false);
+        let mut chars = line.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\'' if !double => single = !single,
+                '"' if !single => double = !double,
+                '\\' if !single => {
+                    out.push(c);
+                    if let Some((_, c)) = chars.next() {
+                        out.push(c);
+                    }
+                    continue;
+                }
+                '!' if !single => {
+                    let rest = &line[i + 1..];
+                    let Some(len) = event_len(rest) else {
+                        out.push(c);
+                        continue;
+                    };
+                    let event = &rest[..len];
+                    match self.event(event) {
+                        Some(text) => out.push_str(&text),
+                        None => return Err(EventNotFound(format!("!{}", event))),
+                    }
+                    // `len` is in bytes, skip the characters that make it up
+                    while chars.clone().next().is_some_and(|(j, _)| j <= i + len) {
+                        chars.next();
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            out.push(c);
+        }
+        Ok(out)
+    }
+
+    // the last word of the entry at `index`, as it was typed
+    pub fn last_word(&self, index: usize) -> Option<&str> {
+        words(self.get(index)?).last().copied()
+    }
+
+    // what the history reference `!event` stands for
+    fn event(&self, event: &str) -> Option<String> {
+        let last = self.entries.last().map(|entry| entry.line.as_str());
+        match event {
+            "!" => last.map(str::to_owned),
+            "$" => last.and_then(|entry| words(entry).last().map(|&word| word.to_owned())),
+            _ => {
+                let index = if let Some(back) = event.strip_prefix('-') {
+                    let back = back.parse::<usize>().ok()?;
+                    self.entries.len().checked_sub(back)?
+                } else if let Ok(number) = event.parse::<usize>() {
+                    number.checked_sub(self.number(0))?
+                } else {
+                    self.entries
+                        .iter()
+                        .rposition(|entry| entry.line.starts_with(event))?
+                };
+                self.get(index).map(str::to_owned)
+            }
+        }
+    }
+
+    // adds the entries in the history file at `path` that haven't been read yet, from other
+    // shells sharing it once it has been loaded; a missing file is an empty history
+    pub fn sync(&mut self, path: &str) -> io::Result<()> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        sys::lock(file.as_raw_fd())?;
+        self.read_new(&mut file, self.entries.len())
+    }
+
+    // adds the newest entry to the end of the history file at `path`, after a `#<seconds>`
+    // line when `timed`; the entries other shells added first are read in before it
+    pub fn append(&mut self, path: &str, timed: bool) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        sys::lock(file.as_raw_fd())?;
+        self.read_new(&mut file, self.entries.len().saturating_sub(1))?;
+        let Some(entry) = self.entries.last() else {
+            return Ok(());
+        };
+        let mut text = String::new();
+        if let Some(time) = entry.time.filter(|_| timed) {
+            text.push_str(&format!("#{}\n", time));
+        }
+        text.push_str(&entry.line);
+        text.push('\n');
+        file.write_all(text.as_bytes())?;
+        self.synced += text.len() as u64;
+        Ok(())
+    }
+
+    // reads the history file's lines past `synced` into entries at `at`, each with the time in
+    // the `#<seconds>` line before it if there is one
+    fn read_new(&mut self, file: &mut File, at: usize) -> io::Result<()> {
+        let end = file.seek(SeekFrom::End(0))?;
+        // another shell cut the file down, what's left was read before
+        if end < self.synced {
+            self.synced = end;
+            return Ok(());
+        }
+        file.seek(SeekFrom::Start(self.synced))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        self.synced = end;
+        let newer = self.entries.split_off(at);
+        let mut time = None;
+        for line in String::from_utf8_lossy(&bytes).lines() {
+            match timestamp(line) {
+                Some(stamp) => time = Some(stamp),
+                None => {
+                    self.add(line, time.take());
+                }
+            }
+        }
+        self.entries.extend(newer);
+        Ok(())
+    }
+}
+
+// the length of the history reference after a `!` at the start of `rest`, `None` when the `!`
+// stands for itself
+fn event_len(rest: &str) -> Option<usize> {
+    let ends = |c: char| c.is_whitespace() || ";&|<>()\"'".contains(c);
+    let first = rest.chars().next().filter(|&c| !ends(c) && c != '=')?;
+    let len = match first {
+        '!' | '$' => 1,
+        '-' | '0'..='9' => {
+            let digits = rest[1..].find(|c: char| !c.is_ascii_digit());
+            1 + digits.unwrap_or(rest.len() - 1)
+        }
+        _ => rest.find(ends).unwrap_or(rest.len()),
+    };
+    Some(len)
+}
+
+// the words of `line`, split at blanks outside of quotes and keeping their quotes
+fn words(line: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut quote = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, _) if c.is_whitespace() => {
+                if let Some(start) = start.take() {
+                    words.push(&line[start..i]);
+                }
+                continue;
+            }
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '\\') => {
+                chars.next();
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if let Some(start) = start {
+        words.push(&line[start..]);
+    }
+    words
+}
+
+// the time in a `#<seconds>` line of a history file
+fn timestamp(line: &str) -> Option<u64> {
+    line.strip_prefix('#')?.parse().ok()
+}
+
+// cuts the history file at `path` down to its last `size` entries, which keep their timestamps
+pub fn trim(path: &str, size: usize) -> io::Result<()> {
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    sys::lock(file.as_raw_fd())?;
+    let mut text = String::new();
+    file.read_to_string(&mut text)?;
+    let lines = text.lines().collect::<Vec<_>>();
+    // where each entry starts, with its timestamp
+    let starts = (0..lines.len())
+        .filter(|&i| i == 0 || timestamp(lines[i - 1]).is_none())
+        .collect::<Vec<_>>();
+    if starts.len() <= size {
+        return Ok(());
+    }
+    let start = starts
+        .get(starts.len() - size)
+        .map_or(lines.len(), |&start| start);
+    let mut kept = lines[start..].join("\n");
+    if !kept.is_empty() {
+        kept.push('\n');
+    }
+    file.rewind()?;
+    file.set_len(0)?;
+    file.write_all(kept.as_bytes())
+}
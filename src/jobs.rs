@@ -0,0 +1,187 @@
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::sys::{self, WaitStatus};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Done(i32),
+    Signaled(i32),
+}
+
+impl JobState {
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::Running => 0,
+            Self::Done(code) => *code,
+            Self::Signaled(sig) => 128 + sig,
+        }
+    }
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Running => f.write_str("Running"),
+            Self::Done(0) => f.write_str("Done"),
+            Self::Done(code) => write!(f, "Exit {}", code),
+            Self::Signaled(sig) => f.write_str(sys::signal_description(*sig)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    pub pid: i32,
+    pub cmd: String,
+    pub state: JobState,
+    pub nohup: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Jobs {
+    list: Vec<Job>,
+}
+
+impl Jobs {
+    pub fn push(&mut self, pid: i32, cmd: String) -> usize {
+        let id = self.list.iter().map(|job| job.id).max().unwrap_or_default() + 1;
+        self.list.push(Job {
+            id,
+            pid,
+            cmd,
+            state: JobState::Running,
+            nohup: false,
+        });
+        id
+    }
+
+    // `spec` is a jobspec without the leading `%`: `1`, `%`, `+`, `-`, `name` or `?text`
+    pub fn find(&self, spec: &str) -> Option<&Job> {
+        match spec {
+            "" | "%" | "+" => self.list.last(),
+            "-" => self.list.iter().rev().nth(1).or(self.list.last()),
+            _ => {
+                if let Ok(id) = spec.parse::<usize>() {
+                    return self.list.iter().find(|job| job.id == id);
+                }
+                if let Some(text) = spec.strip_prefix('?') {
+                    return self.list.iter().rev().find(|job| job.cmd.contains(text));
+                }
+                self.list.iter().rev().find(|job| job.cmd.starts_with(spec))
+            }
+        }
+    }
+
+    pub fn find_pid(&self, pid: i32) -> Option<&Job> {
+        self.list.iter().find(|job| job.pid == pid)
+    }
+
+    pub fn ids(&self) -> Vec<usize> {
+        self.list.iter().map(|job| job.id).collect()
+    }
+
+    // blocks until the job finishes, forgets it and returns its exit status
+    pub fn wait(&mut self, id: usize) -> io::Result<i32> {
+        let Some(index) = self.list.iter().position(|job| job.id == id) else {
+            return Ok(127);
+        };
+        let job = &mut self.list[index];
+        if job.state == JobState::Running {
+            job.state = match sys::waitpid(job.pid, 0)? {
+                WaitStatus::Exited(code) => JobState::Done(code),
+                WaitStatus::Signaled(sig) => JobState::Signaled(sig),
+                WaitStatus::StillAlive => JobState::Running,
+            };
+        }
+        Ok(self.list.remove(index).state.code())
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.list.iter().position(|job| job.id == id)?;
+        Some(self.list.remove(index))
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.list.iter_mut().find(|job| job.id == id)
+    }
+
+    pub fn running_ids(&mut self) -> Vec<usize> {
+        self.update();
+        self.list
+            .iter()
+            .filter(|job| job.state == JobState::Running)
+            .map(|job| job.id)
+            .collect()
+    }
+
+    // called when the shell exits, jobs that were disowned never get here
+    pub fn hangup(&mut self) {
+        self.update();
+        for job in self.list.iter() {
+            if job.state != JobState::Running || job.nohup {
+                continue;
+            }
+            let _ = sys::kill(-job.pid, sys::SIGHUP);
+            let _ = sys::kill(-job.pid, sys::SIGCONT);
+        }
+    }
+
+    fn mark(&self, index: usize) -> char {
+        let len = self.list.len();
+        if index + 1 == len {
+            '+'
+        } else if index + 2 == len {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
+    pub fn update(&mut self) {
+        for job in self.list.iter_mut() {
+            if job.state != JobState::Running {
+                continue;
+            }
+            match sys::waitpid(job.pid, sys::WNOHANG) {
+                Ok(WaitStatus::Exited(code)) => job.state = JobState::Done(code),
+                Ok(WaitStatus::Signaled(sig)) => job.state = JobState::Signaled(sig),
+                Ok(WaitStatus::StillAlive) => {}
+                Err(_) => job.state = JobState::Done(0),
+            }
+        }
+    }
+
+    // reports and forgets every job that finished since the last call
+    pub fn notify<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        self.update();
+        let mut index = 0;
+        while index < self.list.len() {
+            if self.list[index].state == JobState::Running {
+                index += 1;
+                continue;
+            }
+            writeln!(out, "{}", JobLine(&self.list[index], self.mark(index)))?;
+            self.list.remove(index);
+        }
+        out.flush()
+    }
+}
+
+struct JobLine<'a>(&'a Job, char);
+
+impl fmt::Display for JobLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self(job, mark) = self;
+        write!(
+            f,
+            "[{}]{}  {:<24}{}",
+            job.id,
+            mark,
+            job.state.to_string(),
+            job.cmd
+        )
+    }
+}
@@ -0,0 +1,994 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::vars;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordPart {
+    // unquoted text
+    Literal(String),
+    // text inside '...' or escaped with `\`, never expanded
+    Quoted(String),
+    DoubleQuoted(Vec<WordPart>),
+    Param(Param),
+    // `$(...)` or `...` in backquotes
+    Command(String),
+    // `$((...))`
+    Arith(Word),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    pub op: ParamOp,
+}
+
+// the operators of `${name<op>word}`, the flag is set for the `:` forms which
+// also treat an empty value as unset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamOp {
+    None,
+    Default(bool, Word),
+    Assign(bool, Word),
+    Alternate(bool, Word),
+    Error(bool, Word),
+    // `${#name}`
+    Length,
+    // `${name:offset}` and `${name:offset:length}`
+    Substring(Word, Option<Word>),
+    // `${name#pat}` and `${name##pat}`, the flag selects the longest match
+    RemovePrefix(bool, Word),
+    // `${name%pat}` and `${name%%pat}`
+    RemoveSuffix(bool, Word),
+    // `${name/pat/repl}` and its `//`, `/#` and `/%` forms
+    Replace(Replace, Word, Word),
+    // text that could not be parsed, reported as a bad substitution when expanded
+    Bad(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replace {
+    First,
+    All,
+    Prefix,
+    Suffix,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (colon, op, word) = match &self.op {
+            ParamOp::None => return write!(f, "${{{}}}", self.name),
+            ParamOp::Bad(raw) => return write!(f, "${{{}}}", raw),
+            ParamOp::Length => return write!(f, "${{#{}}}", self.name),
+            ParamOp::Substring(offset, length) => {
+                write!(f, "${{{}:{}", self.name, offset.unquoted())?;
+                if let Some(length) = length {
+                    write!(f, ":{}", length.unquoted())?;
+                }
+                return f.write_str("}");
+            }
+            ParamOp::RemovePrefix(longest, word) => {
+                let op = if *longest { "##" } else { "#" };
+                return write!(f, "${{{}{}{}}}", self.name, op, word.unquoted());
+            }
+            ParamOp::RemoveSuffix(longest, word) => {
+                let op = if *longest { "%%" } else { "%" };
+                return write!(f, "${{{}{}{}}}", self.name, op, word.unquoted());
+            }
+            ParamOp::Replace(mode, pattern, repl) => {
+                let op = match mode {
+                    Replace::First => "/",
+                    Replace::All => "//",
+                    Replace::Prefix => "/#",
+                    Replace::Suffix => "/%",
+                };
+                return write!(
+                    f,
+                    "${{{}{}{}/{}}}",
+                    self.name,
+                    op,
+                    pattern.unquoted(),
+                    repl.unquoted()
+                );
+            }
+            ParamOp::Default(colon, word) => (colon, '-', word),
+            ParamOp::Assign(colon, word) => (colon, '=', word),
+            ParamOp::Alternate(colon, word) => (colon, '+', word),
+            ParamOp::Error(colon, word) => (colon, '?', word),
+        };
+        let colon = if *colon { ":" } else { "" };
+        write!(f, "${{{}{}{}{}}}", self.name, colon, op, word.unquoted())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Word(pub Vec<WordPart>);
+
+impl Word {
+    // the text of a word made only of unquoted characters, used to recognise operators
+    pub fn literal(&self) -> Option<&str> {
+        match self.0.as_slice() {
+            [WordPart::Literal(v)] => Some(v),
+            _ => None,
+        }
+    }
+
+    // `NAME=value` split into the name and the (still unexpanded) value
+    pub fn assignment(&self) -> Option<(&str, Word)> {
+        let Some(WordPart::Literal(first)) = self.0.first() else {
+            return None;
+        };
+        let (name, rest) = first.split_once('=')?;
+        if !vars::is_name(name) {
+            return None;
+        }
+        let mut parts = Vec::with_capacity(self.0.len());
+        if !rest.is_empty() {
+            parts.push(WordPart::Literal(rest.to_owned()));
+        }
+        parts.extend(self.0[1..].iter().cloned());
+        Some((name, Word(parts)))
+    }
+
+    // the word with quotes removed and nothing expanded
+    pub fn unquoted(&self) -> String {
+        let mut out = String::new();
+        unquoted_parts(&self.0, &mut out);
+        out
+    }
+}
+
+// the word as it could be typed back in, to show a command in the jobs list
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        source_parts(&self.0, false, f)
+    }
+}
+
+fn source_parts(parts: &[WordPart], in_quotes: bool, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for part in parts {
+        match part {
+            WordPart::Literal(v) => f.write_str(v)?,
+            WordPart::Quoted(v) if in_quotes => {
+                for c in v.chars() {
+                    write!(f, "\\{}", c)?;
+                }
+            }
+            WordPart::Quoted(v) => write!(f, "'{}'", v.replace('\'', "'\\''"))?,
+            WordPart::DoubleQuoted(parts) => {
+                f.write_str("\"")?;
+                source_parts(parts, true, f)?;
+                f.write_str("\"")?;
+            }
+            WordPart::Param(param) => write!(f, "{}", param)?,
+            WordPart::Command(cmd) => write!(f, "$({})", cmd)?,
+            WordPart::Arith(expr) => {
+                f.write_str("$((")?;
+                source_parts(&expr.0, false, f)?;
+                f.write_str("))")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn unquoted_parts(parts: &[WordPart], out: &mut String) {
+    for part in parts {
+        match part {
+            WordPart::Literal(v) | WordPart::Quoted(v) => out.push_str(v),
+            WordPart::DoubleQuoted(parts) => unquoted_parts(parts, out),
+            WordPart::Param(param) => out.push_str(&param.to_string()),
+            WordPart::Command(cmd) => {
+                out.push_str("$(");
+                out.push_str(cmd);
+                out.push(')');
+            }
+            WordPart::Arith(expr) => {
+                out.push_str("$((");
+                unquoted_parts(&expr.0, out);
+                out.push_str("))");
+            }
+        }
+    }
+}
+
+fn is_blank(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r')
+}
+
+fn is_redirect(c: char) -> bool {
+    matches!(c, '<' | '>')
+}
+
+// `text` as a single word where blanks and operators are just characters, like the
+// inside of `$((...))`
+pub fn unsplit(text: &str) -> Word {
+    Word(Lexer::new(text).parts_until(|_| false))
+}
+
+fn is_operator(c: char) -> bool {
+    is_redirect(c) || matches!(c, '&' | '|' | ';' | '(' | ')' | '\n')
+}
+
+fn push_literal(parts: &mut Vec<WordPart>, c: char) {
+    if let Some(WordPart::Literal(v)) = parts.last_mut() {
+        v.push(c);
+    } else {
+        parts.push(WordPart::Literal(c.to_string()));
+    }
+}
+
+fn push_quoted(parts: &mut Vec<WordPart>, c: char) {
+    if let Some(WordPart::Quoted(v)) = parts.last_mut() {
+        v.push(c);
+    } else {
+        parts.push(WordPart::Quoted(c.to_string()));
+    }
+}
+
+pub struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    // set when the input ended where more is expected, like inside quotes or after a
+    // trailing `\`
+    incomplete: bool,
+    // set after a `<<` or `<<-` until its delimiter word has been lexed
+    delimiter_next: Option<bool>,
+    // here-documents whose bodies start after the next newline
+    pending: Vec<Heredoc>,
+    // the bodies of the here-documents read so far, in order
+    heredocs: Vec<Word>,
+    // words already lexed that come out next, last first
+    queued: Vec<Word>,
+    // set after a `=~`, whose regular expression can hold `(`, `)` and `|`
+    regex_next: bool,
+}
+
+#[derive(Debug)]
+struct Heredoc {
+    delimiter: String,
+    // `<<-` strips leading tabs
+    strip_tabs: bool,
+    // a quoted delimiter turns off expansion in the body
+    quoted: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(value: &'a str) -> Self {
+        Self {
+            chars: value.chars().peekable(),
+            incomplete: false,
+            delimiter_next: None,
+            pending: Vec::new(),
+            heredocs: Vec::new(),
+            queued: Vec::new(),
+            regex_next: false,
+        }
+    }
+
+    // the here-document bodies, for the `<<` operators in the order they were lexed
+    pub fn heredocs(self) -> Vec<Word> {
+        self.heredocs
+    }
+
+    // whether the input so far needs more lines before it can be run
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete
+    }
+
+    // called after a `$`, returns `None` when the `$` is just a literal character
+    fn dollar(&mut self) -> Option<WordPart> {
+        let name = match self.chars.peek() {
+            Some('?' | '@' | '*' | '#' | '$' | '!') => self.chars.next().unwrap().to_string(),
+            Some('{') => {
+                self.chars.next();
+                return Some(WordPart::Param(self.braced_param()));
+            }
+            Some('(') => {
+                self.chars.next();
+                if self.chars.next_if_eq(&'(').is_none() {
+                    return Some(WordPart::Command(self.until_close_paren()));
+                }
+                let inner = self.until_close_paren();
+                if self.chars.next_if_eq(&')').is_some() {
+                    return Some(WordPart::Arith(unsplit(&inner)));
+                }
+                // `$( (cmd) ...)` is a command substitution starting with a subshell
+                let rest = self.until_close_paren();
+                return Some(WordPart::Command(format!("({}){}", inner, rest)));
+            }
+            Some(c) if c.is_ascii_alphabetic() || *c == '_' => self.name(),
+            // only one digit, `$10` is `$1` followed by a 0
+            Some(c) if c.is_ascii_digit() => self.chars.next().unwrap().to_string(),
+            _ => return None,
+        };
+        Some(WordPart::Param(Param {
+            name,
+            op: ParamOp::None,
+        }))
+    }
+
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            name.push(c);
+        }
+        name
+    }
+
+    // everything after `${` up to and including the closing `}`
+    fn braced_param(&mut self) -> Param {
+        let length = self.chars.next_if_eq(&'#').is_some();
+        // `${#}` is the number of positional parameters rather than a length
+        if length && self.chars.next_if_eq(&'}').is_some() {
+            return Param {
+                name: "#".to_owned(),
+                op: ParamOp::None,
+            };
+        }
+        let mut name = match self.chars.peek() {
+            Some('?' | '@' | '*' | '$' | '!') => self.chars.next().unwrap().to_string(),
+            _ => self.name(),
+        };
+        // an array subscript stays part of the name, as `name[index]`
+        if !name.is_empty() && self.chars.next_if_eq(&'[').is_some() {
+            name.push('[');
+            while let Some(c) = self.chars.next_if(|c| *c != ']' && *c != '}') {
+                name.push(c);
+            }
+            if self.chars.next_if_eq(&']').is_none() {
+                return self.bad_param(name);
+            }
+            name.push(']');
+        }
+        if length {
+            if name.is_empty() || self.chars.next_if_eq(&'}').is_none() {
+                return self.bad_param(format!("#{}", name));
+            }
+            return Param {
+                name,
+                op: ParamOp::Length,
+            };
+        }
+        let digits = name.bytes().all(|c| c.is_ascii_digit());
+        if name.is_empty() || (name.starts_with(|c: char| c.is_ascii_digit()) && !digits) {
+            return self.bad_param(name);
+        }
+        let colon = self.chars.next_if_eq(&':').is_some();
+        if colon && self.chars.peek().is_some_and(|c| !"-=+?}".contains(*c)) {
+            let offset = Word(self.parts_until(|c| c == ':' || c == '}'));
+            let length = match self.chars.next() {
+                Some(':') => Some(self.param_word()),
+                Some(_) => None,
+                None => {
+                    self.incomplete = true;
+                    None
+                }
+            };
+            return Param {
+                name,
+                op: ParamOp::Substring(offset, length),
+            };
+        }
+        let op = match self.chars.next() {
+            Some('}') if !colon => ParamOp::None,
+            Some('-') => ParamOp::Default(colon, self.param_word()),
+            Some('=') => ParamOp::Assign(colon, self.param_word()),
+            Some('+') => ParamOp::Alternate(colon, self.param_word()),
+            Some('?') => ParamOp::Error(colon, self.param_word()),
+            Some('#') if !colon => {
+                let longest = self.chars.next_if_eq(&'#').is_some();
+                ParamOp::RemovePrefix(longest, self.param_word())
+            }
+            Some('%') if !colon => {
+                let longest = self.chars.next_if_eq(&'%').is_some();
+                ParamOp::RemoveSuffix(longest, self.param_word())
+            }
+            Some('/') if !colon => {
+                let mode = match self.chars.next_if(|c| matches!(c, '/' | '#' | '%')) {
+                    Some('/') => Replace::All,
+                    Some('#') => Replace::Prefix,
+                    Some('%') => Replace::Suffix,
+                    _ => Replace::First,
+                };
+                let pattern = Word(self.parts_until(|c| c == '/' || c == '}'));
+                let repl = match self.chars.next() {
+                    Some('/') => self.param_word(),
+                    Some(_) => Word::default(),
+                    None => {
+                        self.incomplete = true;
+                        Word::default()
+                    }
+                };
+                ParamOp::Replace(mode, pattern, repl)
+            }
+            c => {
+                let mut raw = name;
+                if colon {
+                    raw.push(':');
+                }
+                raw.extend(c.filter(|c| *c != '}'));
+                return if c == Some('}') {
+                    Param {
+                        name: String::new(),
+                        op: ParamOp::Bad(raw),
+                    }
+                } else {
+                    self.bad_param(raw)
+                };
+            }
+        };
+        Param { name, op }
+    }
+
+    // the raw text up to the `)` matching an already consumed `(`, the `)` ending a pattern of
+    // a `case` inside it, or one in a comment or here-document body, doesn't count
+    fn until_close_paren(&mut self) -> String {
+        let mut out = String::new();
+        let mut depth = 0;
+        // for each `case` being read, whether its patterns or commands are next, `None`
+        // before its `in`
+        let mut cases: Vec<Option<bool>> = Vec::new();
+        let mut word = String::new();
+        // whether a word here would be a command name, where `case` and `esac` are reserved
+        let mut command_next = true;
+        // the delimiters of here-documents whose bodies start after the next newline, and
+        // whether they strip leading tabs
+        let mut pending: Vec<(String, bool)> = Vec::new();
+        while let Some(c) = self.chars.next() {
+            let word_start = out.chars().last().map_or(true, |p| {
+                p.is_whitespace() || matches!(p, ';' | '&' | '|' | '(' | ')')
+            });
+            if c.is_alphanumeric() || c == '_' {
+                word.push(c);
+                out.push(c);
+                continue;
+            }
+            if !word.is_empty() {
+                let patterns = cases.last() == Some(&Some(true));
+                match (word.as_str(), cases.last()) {
+                    ("case", _) if command_next => cases.push(None),
+                    ("in", Some(None)) => *cases.last_mut().unwrap() = Some(true),
+                    ("esac", Some(_)) if command_next || patterns => {
+                        cases.pop();
+                    }
+                    _ => {}
+                }
+                command_next = matches!(
+                    word.as_str(),
+                    "if" | "then" | "else" | "elif" | "while" | "until" | "do"
+                );
+                word.clear();
+            }
+            match c {
+                ' ' | '\t' => {}
+                ';' | '\n' | '|' | '&' | '(' => command_next = true,
+                _ => command_next = false,
+            }
+            match c {
+                ')' if depth == 0 && cases.last() == Some(&Some(true)) => {
+                    *cases.last_mut().unwrap() = Some(false);
+                    command_next = true;
+                }
+                ';' if self.chars.peek() == Some(&';') && cases.last() == Some(&Some(false)) => {
+                    *cases.last_mut().unwrap() = Some(true);
+                }
+                '(' => depth += 1,
+                ')' if depth == 0 => return out,
+                ')' => depth -= 1,
+                '#' if word_start => {
+                    out.push(c);
+                    while let Some(c) = self.chars.next_if(|c| *c != '\n') {
+                        out.push(c);
+                    }
+                    continue;
+                }
+                '<' if self.chars.peek() == Some(&'<') => {
+                    out.push(c);
+                    out.extend(self.chars.next());
+                    // `<<<` is a here-string
+                    if let Some(c) = self.chars.next_if_eq(&'<') {
+                        out.push(c);
+                        continue;
+                    }
+                    let strip_tabs = self.chars.next_if_eq(&'-').is_some();
+                    if strip_tabs {
+                        out.push('-');
+                    }
+                    while let Some(c) = self.chars.next_if(|c| is_blank(*c)) {
+                        out.push(c);
+                    }
+                    let delimiter = self.raw_delimiter(&mut out);
+                    if !delimiter.is_empty() {
+                        pending.push((delimiter, strip_tabs));
+                    }
+                    continue;
+                }
+                '\n' if !pending.is_empty() => {
+                    out.push(c);
+                    for (delimiter, strip_tabs) in std::mem::take(&mut pending) {
+                        self.raw_heredoc(&delimiter, strip_tabs, &mut out);
+                    }
+                    continue;
+                }
+                '\\' => {
+                    out.push(c);
+                    out.extend(self.chars.next());
+                    continue;
+                }
+                '\'' | '"' => {
+                    out.push(c);
+                    while let Some(v) = self.chars.next() {
+                        out.push(v);
+                        if v == c {
+                            break;
+                        }
+                        if v == '\\' && c == '"' {
+                            out.extend(self.chars.next());
+                        }
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            out.push(c);
+        }
+        self.incomplete = true;
+        out
+    }
+
+    // the delimiter word after a `<<` inside `$(...)`, copied to `out` as it is and returned
+    // with its quotes removed
+    fn raw_delimiter(&mut self, out: &mut String) -> String {
+        let mut delimiter = String::new();
+        while let Some(c) = self.chars.next_if(|c| {
+            !is_blank(*c) && !matches!(c, '\n' | ';' | '&' | '|' | '(' | ')' | '<' | '>')
+        }) {
+            out.push(c);
+            match c {
+                '\'' | '"' => {
+                    for v in self.chars.by_ref() {
+                        out.push(v);
+                        if v == c {
+                            break;
+                        }
+                        delimiter.push(v);
+                    }
+                }
+                '\\' => {
+                    if let Some(v) = self.chars.next() {
+                        out.push(v);
+                        delimiter.push(v);
+                    }
+                }
+                _ => delimiter.push(c),
+            }
+        }
+        delimiter
+    }
+
+    // copies the lines of a here-document body inside `$(...)` to `out`, up to and including
+    // its delimiter line
+    fn raw_heredoc(&mut self, delimiter: &str, strip_tabs: bool, out: &mut String) {
+        loop {
+            if self.chars.peek().is_none() {
+                self.incomplete = true;
+                return;
+            }
+            let mut line = String::new();
+            while let Some(c) = self.chars.next_if(|c| *c != '\n') {
+                line.push(c);
+            }
+            out.push_str(&line);
+            out.extend(self.chars.next());
+            let line = match strip_tabs {
+                true => line.trim_start_matches('\t'),
+                false => &line,
+            };
+            if line == delimiter {
+                return;
+            }
+        }
+    }
+
+    // the text of a `...` command, `\$`, `\`` and `\\` lose their backslash
+    fn backquoted(&mut self) -> String {
+        let mut out = String::new();
+        while let Some(c) = self.chars.next() {
+            match c {
+                '`' => return out,
+                '\\' => match self.chars.next_if(|v| matches!(v, '$' | '`' | '\\')) {
+                    Some(v) => out.push(v),
+                    None => out.push(c),
+                },
+                _ => out.push(c),
+            }
+        }
+        self.incomplete = true;
+        out
+    }
+
+    fn param_word(&mut self) -> Word {
+        let parts = self.parts_until(|c| c == '}');
+        self.close('}');
+        Word(parts)
+    }
+
+    // consumes the closing `c`, noting when the input ended before it
+    fn close(&mut self, c: char) {
+        if self.chars.next_if_eq(&c).is_none() {
+            self.incomplete = true;
+        }
+    }
+
+    fn bad_param(&mut self, mut raw: String) -> Param {
+        while let Some(c) = self.chars.next_if(|c| *c != '}') {
+            raw.push(c);
+        }
+        self.close('}');
+        Param {
+            name: String::new(),
+            op: ParamOp::Bad(raw),
+        }
+    }
+
+    // lexes word parts until an unquoted character matching `end`, which is left unconsumed
+    fn parts_until(&mut self, end: fn(char) -> bool) -> Vec<WordPart> {
+        let mut parts = Vec::new();
+        while let Some(c) = self.chars.next_if(|c| !end(*c)) {
+            match c {
+                // keeps `@(a|b)` whole where a `|` would end the word
+                '?' | '*' | '+' | '@' | '!' if end('|') && self.chars.peek() == Some(&'(') => {
+                    push_literal(&mut parts, c);
+                    self.ext_group(&mut parts);
+                }
+                '\\' => match self.chars.next() {
+                    // a backslash-newline joins the lines, at the end of the input the next line
+                    // is still to come
+                    Some('\n') => self.incomplete |= self.chars.peek().is_none(),
+                    Some(v) => push_quoted(&mut parts, v),
+                    None => self.incomplete = true,
+                },
+                '\'' => {
+                    let mut quoted = String::new();
+                    while let Some(v) = self.chars.next_if(|c| *c != '\'') {
+                        quoted.push(v);
+                    }
+                    self.close('\'');
+                    parts.push(WordPart::Quoted(quoted));
+                }
+                '"' => parts.push(self.double_quoted()),
+                '`' => parts.push(WordPart::Command(self.backquoted())),
+                '$' if self.chars.next_if_eq(&'\'').is_some() => {
+                    parts.push(WordPart::Quoted(self.ansi_c_quoted()))
+                }
+                '$' => match self.dollar() {
+                    Some(part) => parts.push(part),
+                    None => push_literal(&mut parts, c),
+                },
+                _ => push_literal(&mut parts, c),
+            }
+        }
+        parts
+    }
+
+    // everything after `$'` up to and including the closing `'`, with backslash escapes
+    // decoded; the text stops at a `\0` like it would in C
+    fn ansi_c_quoted(&mut self) -> String {
+        let mut out = String::new();
+        let mut ended = false;
+        while let Some(c) = self.chars.next() {
+            let c = match c {
+                '\'' => return out,
+                '\\' => match self.chars.next() {
+                    Some('a') => '\x07',
+                    Some('b') => '\x08',
+                    Some('e' | 'E') => '\x1b',
+                    Some('f') => '\x0c',
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('v') => '\x0b',
+                    Some(v @ ('\\' | '\'' | '"' | '?')) => v,
+                    Some(v @ '0'..='7') => {
+                        let mut value = v.to_digit(8).unwrap();
+                        for _ in 0..2 {
+                            match self.chars.next_if(|c| c.is_digit(8)) {
+                                Some(c) => value = value * 8 + c.to_digit(8).unwrap(),
+                                None => break,
+                            }
+                        }
+                        char::from_u32(value & 0xff).unwrap_or_default()
+                    }
+                    Some(v @ ('x' | 'u' | 'U')) => {
+                        let max = match v {
+                            'x' => 2,
+                            'u' => 4,
+                            _ => 8,
+                        };
+                        let mut digits = String::new();
+                        while digits.len() < max {
+                            match self.chars.next_if(|c| c.is_ascii_hexdigit()) {
+                                Some(c) => digits.push(c),
+                                None => break,
+                            }
+                        }
+                        match u32::from_str_radix(&digits, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                        {
+                            Some(c) => c,
+                            None => {
+                                out.push('\\');
+                                out.push(v);
+                                continue;
+                            }
+                        }
+                    }
+                    Some('c') => match self.chars.next() {
+                        Some(v) => char::from(v.to_ascii_uppercase() as u8 ^ 0x40),
+                        None => '\\',
+                    },
+                    Some(v) => {
+                        if !ended {
+                            out.push('\\');
+                        }
+                        v
+                    }
+                    None => '\\',
+                },
+                _ => c,
+            };
+            ended |= c == '\0';
+            if !ended {
+                out.push(c);
+            }
+        }
+        self.incomplete = true;
+        out
+    }
+
+    // a word where `(`, `)`, `|`, `<` and `>` are ordinary characters, and blanks too
+    // inside parentheses
+    fn regex_word(&mut self) -> Word {
+        let mut parts = Vec::new();
+        let mut depth = 0;
+        loop {
+            parts.extend(self.parts_until(|c| is_blank(c) || is_operator(c)));
+            match self.chars.peek() {
+                Some('(') => depth += 1,
+                Some(')') if depth > 0 => depth -= 1,
+                Some('|' | '<' | '>') => {}
+                Some(' ' | '\t') if depth > 0 => {}
+                _ => break,
+            }
+            push_literal(&mut parts, self.chars.next().unwrap());
+        }
+        Word(parts)
+    }
+
+    // the parenthesised part of an extglob pattern, as literal text
+    fn ext_group(&mut self, parts: &mut Vec<WordPart>) {
+        let mut depth = 0;
+        for c in self.chars.by_ref() {
+            push_literal(parts, c);
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 1 => return,
+                ')' => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    fn double_quoted(&mut self) -> WordPart {
+        WordPart::DoubleQuoted(self.expanded_parts(true))
+    }
+
+    // the parts of a double-quoted string up to the closing `"`, or of a here-document
+    // body when not `in_quotes`
+    fn expanded_parts(&mut self, in_quotes: bool) -> Vec<WordPart> {
+        let mut parts = Vec::new();
+        while let Some(c) = self.chars.next() {
+            match c {
+                '"' if in_quotes => return parts,
+                '\\' => match self.chars.peek() {
+                    Some('$' | '`' | '\\') => {
+                        push_quoted(&mut parts, self.chars.next().unwrap());
+                    }
+                    Some('"') if in_quotes => {
+                        push_quoted(&mut parts, self.chars.next().unwrap());
+                    }
+                    Some('\n') => {
+                        self.chars.next();
+                    }
+                    _ => push_quoted(&mut parts, c),
+                },
+                '`' => parts.push(WordPart::Command(self.backquoted())),
+                '$' => match self.dollar() {
+                    Some(part) => parts.push(part),
+                    None => push_quoted(&mut parts, c),
+                },
+                _ => push_quoted(&mut parts, c),
+            }
+        }
+        self.incomplete |= in_quotes;
+        parts
+    }
+
+    fn operator(&mut self) -> String {
+        if self.chars.next_if_eq(&'\n').is_some() {
+            self.read_heredocs();
+            return String::from("\n");
+        }
+        if let Some(c) = self.chars.next_if(|c| matches!(c, '(' | ')')) {
+            return c.to_string();
+        }
+        if self.chars.next_if_eq(&';').is_some() {
+            // `;;`, `;&` and `;;&` end the branches of a `case`
+            let mut op = String::from(";");
+            if self.chars.next_if_eq(&';').is_some() {
+                op.push(';');
+            }
+            op.extend(self.chars.next_if_eq(&'&'));
+            return op;
+        }
+        if self.chars.next_if_eq(&'|').is_some() {
+            // `|&` pipes stderr along with stdout
+            let mut op = String::from("|");
+            op.extend(self.chars.next_if(|c| matches!(c, '|' | '&')));
+            return op;
+        }
+        if self.chars.next_if_eq(&'&').is_none() {
+            return self.redirect_operator(String::new());
+        }
+        let mut op = String::from("&");
+        match self.chars.next_if(|c| matches!(c, '&' | '>')) {
+            // `&>` and `&>>` redirect stdout and stderr together
+            Some('>') => {
+                op.push('>');
+                op.extend(self.chars.next_if_eq(&'>'));
+            }
+            Some(c) => op.push(c),
+            None => {}
+        }
+        op
+    }
+
+    // `<`, `>` and the operators starting with them, after an optional fd number
+    fn redirect_operator(&mut self, mut op: String) -> String {
+        match self.chars.next() {
+            Some('<') => {
+                op.push('<');
+                if self.chars.next_if_eq(&'<').is_some() {
+                    op.push('<');
+                    if self.chars.next_if_eq(&'<').is_some() {
+                        op.push('<');
+                    } else if self.chars.next_if_eq(&'-').is_some() {
+                        op.push('-');
+                        self.delimiter_next = Some(true);
+                    } else {
+                        self.delimiter_next = Some(false);
+                    }
+                } else {
+                    op.extend(self.chars.next_if_eq(&'&'));
+                }
+            }
+            Some(c) => {
+                op.push(c);
+                op.extend(self.chars.next_if(|c| matches!(c, '>' | '&' | '|')));
+            }
+            None => {}
+        }
+        op
+    }
+
+    // the expression of `((...))` at the start of a word, which is only arithmetic when the
+    // parentheses close together, `((cmd); cmd)` is a subshell in a subshell
+    fn arith_command(&mut self) -> Option<Word> {
+        let mut ahead = self.chars.clone();
+        if ahead.next() != Some('(') || ahead.next() != Some('(') {
+            return None;
+        }
+        let mut probe = Lexer::new("");
+        probe.chars = ahead;
+        let inner = probe.until_close_paren();
+        if probe.incomplete {
+            self.incomplete = true;
+        } else if probe.chars.next_if_eq(&')').is_none() {
+            return None;
+        }
+        self.chars = probe.chars;
+        Some(unsplit(&inner))
+    }
+
+    // reads the bodies of the pending here-documents, which follow the line they were
+    // started on
+    fn read_heredocs(&mut self) {
+        for heredoc in std::mem::take(&mut self.pending) {
+            let mut body = String::new();
+            loop {
+                if self.chars.peek().is_none() {
+                    self.incomplete = true;
+                    break;
+                }
+                let mut line = String::new();
+                while let Some(c) = self.chars.next_if(|c| *c != '\n') {
+                    line.push(c);
+                }
+                let ended = self.chars.next().is_none();
+                let line = match heredoc.strip_tabs {
+                    true => line.trim_start_matches('\t'),
+                    false => &line,
+                };
+                if line == heredoc.delimiter {
+                    break;
+                }
+                body.push_str(line);
+                body.push('\n');
+                if ended {
+                    self.incomplete = true;
+                    break;
+                }
+            }
+            let part = match heredoc.quoted {
+                true => WordPart::Quoted(body),
+                false => WordPart::DoubleQuoted(Lexer::new(&body).expanded_parts(false)),
+            };
+            self.heredocs.push(Word(vec![part]));
+        }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Word;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(word) = self.queued.pop() {
+            return Some(word);
+        }
+        let delimiter = self.delimiter_next.take();
+        while self.chars.next_if(|c| is_blank(*c)).is_some() {}
+        // a `#` starting a word comments out the rest of the line
+        if self.chars.next_if_eq(&'#').is_some() {
+            while self.chars.next_if(|c| *c != '\n').is_some() {}
+            return self.next();
+        }
+        if self.chars.peek().is_none() {
+            self.incomplete |= !self.pending.is_empty();
+            return None;
+        }
+        if std::mem::take(&mut self.regex_next) {
+            return Some(self.regex_word());
+        }
+        if let Some(expr) = self.arith_command() {
+            self.queued
+                .push(Word(vec![WordPart::Literal(String::from("))"))]));
+            self.queued.push(expr);
+            return Some(Word(vec![WordPart::Literal(String::from("(("))]));
+        }
+        let parts = self.parts_until(|c| is_blank(c) || is_operator(c));
+        let word = match parts.as_slice() {
+            [] => Word(vec![WordPart::Literal(self.operator())]),
+            // digits right before `<` or `>` name the fd being redirected
+            [WordPart::Literal(fd)]
+                if fd.chars().all(|c| c.is_ascii_digit())
+                    && self.chars.peek().is_some_and(|c| is_redirect(*c)) =>
+            {
+                Word(vec![WordPart::Literal(self.redirect_operator(fd.clone()))])
+            }
+            _ => Word(parts),
+        };
+        self.regex_next = word.literal() == Some("=~");
+        if let Some(strip_tabs) = delimiter {
+            self.pending.push(Heredoc {
+                delimiter: word.unquoted(),
+                strip_tabs,
+                quoted: word.literal().is_none(),
+            });
+        }
+        Some(word)
+    }
+}
@@ -1,37 +1,1237 @@
-use std::io::{self, BufWriter, Write};
-use std::iter::{Enumerate, Peekable};
+mod arith;
+mod brace;
+mod builtins;
+mod complete;
+mod cond;
+mod editor;
+mod expand;
+mod glob;
+mod highlight;
+mod history;
+mod jobs;
+mod lexer;
+mod parser;
+mod shell;
+mod sys;
+mod unicode;
+mod vars;
+
+use std::io::{self, BufRead, BufWriter, Write};
+use std::os::fd::{AsRawFd, IntoRawFd, OwnedFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
 use std::process::Stdio;
-use std::str::Chars;
-use std::{borrow::Cow, fmt, fs, path::PathBuf, process, str::FromStr};
+use std::time::SystemTime;
+use std::{borrow::Cow, collections::BTreeMap, fmt, fs, process, rc::Rc};
+
+use editor::Editor;
+use glob::Pattern;
+use lexer::Word;
+use parser::{
+    AndOr, CaseEnd, Command, Compound, CondExpr, List, ParseError, Pipeline, SimpleCommand,
+};
+use shell::{Flow, Shell};
 
 fn main() -> io::Result<()> {
-    let stdin = io::stdin();
-    print!("$ ");
-    io::stdout().flush()?;
+    let mut shell = Shell::new();
+    let mut args = std::env::args();
+    shell.name = args.next().unwrap_or_default();
+    let Some(script) = args.next() else {
+        // commands piped in or read from a here-document get no prompts
+        shell.interactive = sys::isatty(0);
+        match shell.interactive {
+            true => {
+                // Ctrl-C stops the foreground command, which shares the shell's process
+                // group, without ending the shell
+                sys::catch_signal(sys::SIGINT);
+                // the line being edited is drawn again when the terminal is resized
+                sys::catch_signal(sys::SIGWINCH);
+                for sig in shell::SHIELDED_SIGNALS {
+                    sys::ignore_signal(*sig);
+                }
+                // the shell leads a process group of its own, which owns the terminal
+                // whenever no foreground job does
+                let _ = sys::setpgid(0, 0);
+                let _ = sys::tcsetpgrp(0, sys::getpgrp());
+                load_history(&mut shell);
+                shell.options.insert("histexpand");
+                shell.options.insert("emacs");
+                let mut editor = Editor::new();
+                run_input(&mut shell, |shell, more| {
+                    let prompt = match more {
+                        true => Ok(shell.vars.get("PS2").unwrap_or("> ").to_owned()),
+                        false => prompt(shell),
+                    };
+                    let line = prompt.and_then(|prompt| editor.read_line(&prompt, shell));
+                    let line = match line {
+                        Ok(Some(line)) if shell.option("histexpand") => {
+                            Ok(Some(expand_history(shell, line)))
+                        }
+                        line => line,
+                    };
+                    if let Ok(Some(line)) = &line {
+                        add_history(shell, line);
+                    }
+                    line.transpose()
+                })?;
+            }
+            false => {
+                let mut lines = io::stdin().lines();
+                run_input(&mut shell, |_, _| lines.next())?;
+            }
+        }
+        if shell.interactive {
+            shell.jobs.hangup();
+        }
+        exit(&mut shell, None);
+    };
+    // `-c text [name [args...]]`, the words after the text become `$0` and then `$1` onwards
+    if script == "-c" {
+        let Some(text) = args.next() else {
+            eprintln!("-c: option requires an argument");
+            process::exit(2);
+        };
+        if let Some(name) = args.next() {
+            shell.name = name;
+        }
+        shell.args = args.collect();
+        let mut lines = text.lines().map(|line| Ok(line.to_owned()));
+        run_input(&mut shell, |_, _| lines.next())?;
+        exit(&mut shell, None);
+    }
+    let file = match fs::File::open(&script) {
+        Ok(file) if file.metadata().is_ok_and(|meta| meta.is_dir()) => {
+            eprintln!("{}: Is a directory", script);
+            process::exit(126);
+        }
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("{}: {}", script, builtins::os_error(&err));
+            process::exit(match err.kind() {
+                io::ErrorKind::NotFound => 127,
+                _ => 126,
+            });
+        }
+    };
+    shell.name = script;
+    shell.args = args.collect();
+    let mut lines = io::BufReader::new(file).lines();
+    run_input(&mut shell, |_, _| lines.next())?;
+    exit(&mut shell, None)
+}
+
+// ends the shell after running the EXIT trap, where `exit` can still change the status,
+// with `$?` when there's no `status`
+fn exit(shell: &mut Shell, status: Option<i32>) -> ! {
+    let status = status.unwrap_or(shell.last_status);
+    if let Some(command) = shell.traps.remove(&shell::EXIT_TRAP) {
+        shell.last_status = status;
+        let _ = run_line(shell, &command);
+    }
+    if shell.interactive {
+        if let Some(path) = shell.vars.get("HISTFILE") {
+            let size = history_size(shell, "HISTFILESIZE");
+            if let Err(err) = history::trim(path, size) {
+                eprintln!("{}: {}", path, builtins::os_error(&err));
+            }
+        }
+    }
+    process::exit(status)
+}
+
+// gives `HISTFILE`, `HISTSIZE` and `HISTFILESIZE` their defaults and reads in the history
+// saved by earlier shells
+fn load_history(shell: &mut Shell) {
+    if shell.vars.get("HISTFILE").is_none() {
+        if let Some(home) = shell.vars.get("HOME") {
+            let path = format!("{}/.shell_history", home.trim_end_matches('/'));
+            let _ = shell.vars.set("HISTFILE", path);
+        }
+    }
+    for name in ["HISTSIZE", "HISTFILESIZE"] {
+        if shell.vars.get(name).is_none() {
+            let _ = shell.vars.set(name, "500".to_owned());
+        }
+    }
+    if let Some(path) = shell.vars.get("HISTFILE") {
+        if let Err(err) = shell.history.sync(path) {
+            eprintln!("{}: {}", path, builtins::os_error(&err));
+        }
+    }
+    let size = history_size(shell, "HISTSIZE");
+    shell.history.limit(size);
+}
+
+// `line` with its history references replaced, shown when there were any; a reference to
+// nothing throws the line away
+fn expand_history(shell: &Shell, line: String) -> String {
+    match shell.history.expand(&line) {
+        Ok(expanded) if expanded == line => line,
+        Ok(expanded) => {
+            eprintln!("{}", expanded);
+            expanded
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            String::new()
+        }
+    }
+}
+
+// keeps a line typed at the prompt, adding it to `HISTFILE` straight away so that it
+// survives the shell being killed and other shells sharing the file see it
+fn add_history(shell: &mut Shell, line: &str) {
+    // `HISTCONTROL` is a `:` separated list, `ignoreboth` is both of the `ignore` settings
+    let control = shell.vars.get("HISTCONTROL").unwrap_or_default();
+    let control = |name: &str| {
+        control.split(':').any(|setting| {
+            setting == name || (setting == "ignoreboth" && name.starts_with("ignore"))
+        })
+    };
+    let last = shell.history.len().checked_sub(1);
+    if (control("ignorespace") && line.starts_with(' '))
+        || (control("ignoredups") && last.and_then(|last| shell.history.get(last)) == Some(line))
+    {
+        return;
+    }
+    if control("erasedups") {
+        shell.history.erase(line);
+    }
+    if !shell.history.push(line) {
+        return;
+    }
+    if let Some(path) = shell.vars.get("HISTFILE") {
+        // timestamps are only written once they're wanted, as bash does
+        let timed = shell.vars.get("HISTTIMEFORMAT").is_some();
+        if let Err(err) = shell.history.append(path, timed) {
+            eprintln!("{}: {}", path, builtins::os_error(&err));
+        }
+    }
+    let size = history_size(shell, "HISTSIZE");
+    shell.history.limit(size);
+}
+
+// the number of entries the variable `name` allows, a negative or unusable value allows any
+fn history_size(shell: &Shell, name: &str) -> usize {
+    match shell.vars.get(name).map(|size| size.parse::<i64>()) {
+        Some(Ok(size)) => usize::try_from(size).unwrap_or(usize::MAX),
+        _ => usize::MAX,
+    }
+}
+
+// runs commands as their lines come in, `read_line` gets each one told whether it continues
+// an unfinished command; outside an interactive shell a syntax error ends the input
+fn run_input(
+    shell: &mut Shell,
+    mut read_line: impl FnMut(&mut Shell, bool) -> Option<io::Result<String>>,
+) -> io::Result<()> {
+    let interactive = shell.interactive;
+    let mut input = String::new();
+    while let Some(line) = read_line(shell, !input.is_empty()) {
+        let line = match line {
+            // Ctrl-C drops what was typed so far and starts again at a fresh prompt
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                input.clear();
+                shell.last_status = 130;
+                run_traps(shell)?;
+                shell.flow = None;
+                continue;
+            }
+            line => line?,
+        };
+        input.push_str(&line);
+        input.push('\n');
+        match parser::parse(&input) {
+            Err(ParseError::Incomplete) => continue,
+            Err(err) => {
+                eprintln!("{}", err);
+                shell.last_status = 2;
+                if !interactive {
+                    return Ok(());
+                }
+            }
+            Ok(list) if list.is_empty() => {}
+            Ok(list) => shell.last_status = run_list(shell, &list)?,
+        }
+        input.clear();
+        run_traps(shell)?;
+        if shell.flow == Some(Flow::Interrupt) {
+            println!();
+            shell.flow = None;
+        }
+    }
+    // the input ended inside a command
+    if !input.is_empty() {
+        eprintln!("{}", ParseError::Incomplete);
+        shell.last_status = 2;
+    }
+    Ok(())
+}
 
-    for line in stdin.lines() {
-        let line = line?;
-        if line.trim().is_empty() {
-            print!("$ ");
-            io::stdout().flush()?;
+fn run_line(shell: &mut Shell, line: &str) -> io::Result<i32> {
+    match parser::parse(line) {
+        Ok(list) => run_list(shell, &list),
+        Err(err) => {
+            eprintln!("{}", err);
+            Ok(2)
+        }
+    }
+}
+
+const FC_USAGE: &str =
+    "fc: usage: fc [-e ename] [-lnr] [first] [last] or fc -s [pat=rep] [command]";
+
+// `fc -l` lists history entries and `fc -s [old=new] [first]` runs one again with `old`
+// replaced, otherwise the entries are edited with `-e`, `$FCEDIT` or `$EDITOR` and then run
+fn fc(shell: &mut Shell, args: &[Cow<'_, str>]) -> io::Result<i32> {
+    let (mut list, mut numbers, mut reverse, mut again) = (false, true, false, false);
+    let mut editor = None;
+    let mut args = args.iter().map(|arg| arg.as_ref()).peekable();
+    // `-5` is an offset rather than options
+    while let Some(arg) = args.next_if(|arg| {
+        arg.starts_with('-') && arg.len() > 1 && !arg[1..].starts_with(|c: char| c.is_ascii_digit())
+    }) {
+        if arg == "--" {
+            break;
+        }
+        for c in arg[1..].chars() {
+            match c {
+                'l' => list = true,
+                'n' => numbers = false,
+                'r' => reverse = true,
+                's' => again = true,
+                'e' => match args.next() {
+                    Some(name) => editor = Some(name.to_owned()),
+                    None => {
+                        eprintln!("fc: -e: option requires an argument");
+                        eprintln!("{}", FC_USAGE);
+                        return Ok(2);
+                    }
+                },
+                _ => {
+                    eprintln!("fc: -{}: invalid option", c);
+                    eprintln!("{}", FC_USAGE);
+                    return Ok(2);
+                }
+            }
+        }
+    }
+    let mut rest = args.collect::<Vec<_>>();
+    // the newest entry is this `fc` command
+    let end = shell.history.len().saturating_sub(1);
+    if again {
+        let replace = rest.first().and_then(|arg| arg.split_once('='));
+        if replace.is_some() {
+            rest.remove(0);
+        }
+        let spec = rest.first().copied().unwrap_or("-1");
+        let Some(index) = shell.history.lookup(spec, end) else {
+            eprintln!("fc: no command found");
+            return Ok(1);
+        };
+        let mut command = shell.history.get(index).unwrap_or_default().to_owned();
+        if let Some((old, new)) = replace.filter(|(old, _)| !old.is_empty()) {
+            command = command.replace(old, new);
+        }
+        return run_again(shell, &command);
+    }
+    let (first, last) = match rest.as_slice() {
+        [] if list => ("-16", "-1"),
+        [] => ("-1", "-1"),
+        [first] if list => (*first, "-1"),
+        [first] => (*first, *first),
+        [first, last, ..] => (*first, *last),
+    };
+    let (Some(first), Some(last)) = (
+        shell.history.lookup(first, end),
+        shell.history.lookup(last, end),
+    ) else {
+        eprintln!("fc: history specification out of range");
+        return Ok(1);
+    };
+    let mut indexes = (first.min(last)..=first.max(last)).collect::<Vec<_>>();
+    if reverse != (first > last) {
+        indexes.reverse();
+    }
+    if list {
+        for index in indexes {
+            let entry = shell.history.get(index).unwrap_or_default();
+            match numbers {
+                true => println!("{}\t {}", shell.history.number(index), entry),
+                false => println!("\t {}", entry),
+            }
+        }
+        return Ok(0);
+    }
+    let editor = editor
+        .or_else(|| shell.vars.get("FCEDIT").map(str::to_owned))
+        .or_else(|| shell.vars.get("EDITOR").map(str::to_owned))
+        .unwrap_or_else(|| "vi".to_owned());
+    let mut text = String::new();
+    for index in indexes {
+        text.push_str(shell.history.get(index).unwrap_or_default());
+        text.push('\n');
+    }
+    let path = std::env::temp_dir().join(format!("fc-{}", sys::getpid()));
+    fs::write(&path, text)?;
+    let status = run_line(shell, &format!("{} {}", editor, path.display()))?;
+    let text = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+    if status != 0 {
+        return Ok(status);
+    }
+    run_again(shell, text?.trim_end_matches('\n'))
+}
+
+// shows and runs `commands` taken from the history by `fc`, which they replace in the history
+fn run_again(shell: &mut Shell, commands: &str) -> io::Result<i32> {
+    eprintln!("{}", commands);
+    if shell.interactive && shell.history.len() > 0 {
+        shell.history.remove(shell.history.len() - 1);
+        for line in commands.lines() {
+            add_history(shell, line);
+        }
+    }
+    run_line(shell, commands)
+}
+
+// runs a file in this shell, `args` replace the positional parameters while it runs
+fn source(shell: &mut Shell, name: &str, args: &[Cow<'_, str>]) -> io::Result<i32> {
+    let path = match name.contains('/') {
+        true => None,
+        false => find_path(shell, name),
+    };
+    let path = path.as_deref().unwrap_or(name);
+    if fs::metadata(path).is_ok_and(|meta| meta.is_dir()) {
+        eprintln!("source: {}: is a directory", name);
+        return Ok(1);
+    }
+    let mut text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("{}: {}", name, builtins::os_error(&err));
+            return Ok(1);
+        }
+    };
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    let list = match parser::parse(&text) {
+        Ok(list) => list,
+        Err(err) => {
+            eprintln!("{}: {}", name, err);
+            return Ok(2);
+        }
+    };
+    let saved = match args.is_empty() {
+        true => None,
+        false => Some(std::mem::replace(
+            &mut shell.args,
+            args.iter().map(|v| v.to_string()).collect(),
+        )),
+    };
+    shell.sourced += 1;
+    let status = run_list(shell, &list);
+    shell.sourced -= 1;
+    if shell.flow == Some(Flow::Return) {
+        shell.flow = None;
+    }
+    if let Some(saved) = saved {
+        shell.args = saved;
+    }
+    status
+}
+
+fn run_list(shell: &mut Shell, list: &List) -> io::Result<i32> {
+    let mut status = 0;
+    for item in list {
+        status = match item.background {
+            true => run_background(shell, &item.and_or)?,
+            false => run_and_or(shell, &item.and_or)?,
+        };
+        shell.last_status = status;
+        run_traps(shell)?;
+        if shell.flow.is_some() {
+            break;
+        }
+    }
+    Ok(status)
+}
+
+// runs the traps for the signals that arrived since the last check, leaving `$?` alone
+fn run_traps(shell: &mut Shell) -> io::Result<()> {
+    for sig in sys::take_signals() {
+        let Some(command) = shell.traps.get(&sig).cloned() else {
+            // without a trap Ctrl-C abandons the rest of the line, as it did the command
+            if sig == sys::SIGINT && shell.interactive {
+                shell.flow = Some(Flow::Interrupt);
+            }
             continue;
+        };
+        let status = shell.last_status;
+        run_line(shell, &command)?;
+        shell.last_status = status;
+    }
+    Ok(())
+}
+
+// `&&` runs the next pipeline after a success, `||` after a failure
+fn run_and_or(shell: &mut Shell, and_or: &AndOr) -> io::Result<i32> {
+    let mut last = and_or.rest.is_empty();
+    let mut negated = and_or.first.negated;
+    let mut status = match last {
+        true => run_pipeline(shell, &and_or.first)?,
+        false => tested(shell, |shell| run_pipeline(shell, &and_or.first))?,
+    };
+    for (i, (and, pipeline)) in and_or.rest.iter().enumerate() {
+        if shell.flow.is_some() {
+            break;
         }
-        let (redirect_path, args) = get_redirect_path(IterArgs::new(line.as_str()).collect())?;
-        let cmd = Cmd::from(args);
-        cmd.execute(redirect_path)?;
-        print!("$ ");
-        io::stdout().flush()?;
+        if (status == 0) == *and {
+            shell.last_status = status;
+            last = i + 1 == and_or.rest.len();
+            negated = pipeline.negated;
+            status = match last {
+                true => run_pipeline(shell, pipeline)?,
+                false => tested(shell, |shell| run_pipeline(shell, pipeline))?,
+            };
+        }
+    }
+    // only the last pipeline of a list failing counts for the ERR trap and `set -e`, and not
+    // when `!` made it fail
+    if last && !negated {
+        failed(shell, status)?;
+    }
+    Ok(status)
+}
+
+// runs a command whose status is being tested, so `set -e` ignores it failing
+fn tested<T>(shell: &mut Shell, run: impl FnOnce(&mut Shell) -> T) -> T {
+    shell.conditions += 1;
+    let result = run(shell);
+    shell.conditions -= 1;
+    result
+}
+
+// a failed command runs the ERR trap and then under `set -e` ends the shell, unless its
+// status is being tested
+fn failed(shell: &mut Shell, status: i32) -> io::Result<()> {
+    if status == 0 || shell.conditions > 0 || shell.flow.is_some() {
+        return Ok(());
+    }
+    // functions don't see the trap, only their caller does
+    let trap = shell
+        .traps
+        .get(&shell::ERR_TRAP)
+        .filter(|_| !shell.vars.has_scope());
+    if let Some(command) = trap.cloned() {
+        shell.last_status = status;
+        tested(shell, |shell| run_line(shell, &command))?;
+        shell.last_status = status;
+    }
+    if shell.option("errexit") {
+        exit(shell, Some(status));
     }
     Ok(())
 }
 
+fn run_background(shell: &mut Shell, and_or: &AndOr) -> io::Result<i32> {
+    let text = and_or.to_string();
+    if let (Some((Command::Simple(command), _)), true, true) = (
+        and_or.first.commands.first(),
+        and_or.first.commands.len() == 1,
+        and_or.rest.is_empty(),
+    ) {
+        return run_command(shell, command, Some(&text));
+    }
+    // anything bigger runs under its own process group, led by a copy of the shell
+    spawn_job(shell, text, |shell| run_and_or(shell, and_or))
+}
+
+fn spawn_job(
+    shell: &mut Shell,
+    text: String,
+    run: impl FnOnce(&mut Shell) -> io::Result<i32>,
+) -> io::Result<i32> {
+    let pid = sys::fork()?;
+    if pid == 0 {
+        let _ = sys::setpgid(0, 0);
+        shell.subshell();
+        let status = run(shell).unwrap_or(1);
+        sys::exit_child(status);
+    }
+    let _ = sys::setpgid(pid, pid);
+    let id = shell.jobs.push(pid, text);
+    shell.last_background = Some(pid);
+    if shell.interactive {
+        eprintln!("[{}] {}", id, pid);
+    }
+    Ok(0)
+}
+
+fn run(shell: &mut Shell, command: &Command) -> io::Result<i32> {
+    match command {
+        Command::Simple(command) => run_command(shell, command, None),
+        Command::Compound(compound, redirections) if redirections.words.is_empty() => {
+            run_compound(shell, compound)
+        }
+        Command::Compound(compound, redirections) => {
+            match get_redirect_path(shell, &redirections.words, &redirections.heredocs) {
+                Ok((redirection, _)) => {
+                    redirected(shell, redirection, |shell| run_compound(shell, compound))
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    Ok(1)
+                }
+            }
+        }
+        Command::Function(name, body) => {
+            shell.functions.insert(name.clone(), body.clone());
+            Ok(0)
+        }
+        Command::Coproc(name, body) => run_coproc(shell, name, body, command.to_string()),
+    }
+}
+
+// starts `command` as a background job whose stdin and stdout are pipes the shell
+// keeps, with the descriptors stored in `name[1]` and `name[0]`
+fn run_coproc(shell: &mut Shell, name: &str, command: &Command, text: String) -> io::Result<i32> {
+    let (child_in, input) = sys::pipe()?;
+    let (output, child_out) = sys::pipe()?;
+    let pid = sys::fork()?;
+    if pid == 0 {
+        drop((input, output));
+        let _ = sys::setpgid(0, 0);
+        shell.subshell();
+        let status = sys::dup2(child_in.as_raw_fd(), 0)
+            .and_then(|()| sys::dup2(child_out.as_raw_fd(), 1))
+            .and_then(|()| {
+                drop((child_in, child_out));
+                run(shell, command)
+            })
+            .unwrap_or(1);
+        sys::exit_child(status);
+    }
+    drop((child_in, child_out));
+    let _ = sys::setpgid(pid, pid);
+    let fds = vec![
+        lift(output)?.into_raw_fd().to_string(),
+        lift(input)?.into_raw_fd().to_string(),
+    ];
+    let id = shell.jobs.push(pid, text);
+    shell.last_background = Some(pid);
+    if shell.interactive {
+        eprintln!("[{}] {}", id, pid);
+    }
+    let set = shell.vars.set_array(name, fds);
+    if let Err(err) = set.and_then(|()| shell.vars.set(&format!("{}_PID", name), pid.to_string())) {
+        eprintln!("{}", err);
+        return Ok(1);
+    }
+    Ok(0)
+}
+
+// the redirections apply to the shell itself until `run` is done
+fn redirected(
+    shell: &mut Shell,
+    redirection: Redirection<'_>,
+    run: impl FnOnce(&mut Shell) -> io::Result<i32>,
+) -> io::Result<i32> {
+    let fds = match redirection.open() {
+        Ok(fds) => fds,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Ok(1);
+        }
+    };
+    let saved = fds.save()?;
+    fds.persist()?;
+    drop(fds);
+    let status = run(shell);
+    restore(saved)?;
+    status
+}
+
+// runs a function's body with `args` as the positional parameters
+fn call_function(
+    shell: &mut Shell,
+    body: &Command,
+    args: Vec<String>,
+    redirection: Redirection<'_>,
+) -> io::Result<i32> {
+    redirected(shell, redirection, |shell| {
+        let saved = std::mem::replace(&mut shell.args, args);
+        // `break` and `continue` can't reach the caller's loops
+        let loops = std::mem::take(&mut shell.loops);
+        shell.vars.push_scope();
+        let status = run(shell, body);
+        shell.vars.pop_scope();
+        if shell.flow == Some(Flow::Return) {
+            shell.flow = None;
+        }
+        shell.loops = loops;
+        shell.args = saved;
+        status
+    })
+}
+
+fn run_compound(shell: &mut Shell, compound: &Compound) -> io::Result<i32> {
+    match compound {
+        Compound::If(branches, otherwise) => {
+            for (condition, body) in branches {
+                let status = tested(shell, |shell| run_list(shell, condition))?;
+                if shell.flow.is_some() {
+                    return Ok(status);
+                }
+                if status == 0 {
+                    return run_list(shell, body);
+                }
+            }
+            match otherwise {
+                Some(body) => run_list(shell, body),
+                None => Ok(0),
+            }
+        }
+        Compound::Group(list) => run_list(shell, list),
+        Compound::Arith(expr) => {
+            let value = expand::expand_word(shell, expr)
+                .map_err(|err| err.to_string())
+                .and_then(|expr| arith::eval(shell, &expr).map_err(|err| err.to_string()));
+            match value {
+                Ok(value) => Ok((value == 0) as i32),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    Ok(1)
+                }
+            }
+        }
+        Compound::Cond(expr) => Ok(match test_cond(shell, expr) {
+            Some(true) => 0,
+            Some(false) => 1,
+            None => 2,
+        }),
+        Compound::Subshell(list) => {
+            let pid = sys::fork()?;
+            if pid == 0 {
+                foreground_group(shell, 0, sys::getpid());
+                shell.subshell();
+                // the enclosing loops stay behind in the parent
+                shell.loops = 0;
+                let status = run_list(shell, list).unwrap_or(1);
+                sys::exit_child(status);
+            }
+            foreground_group(shell, pid, pid);
+            let status = sys::waitpid(pid, 0)?;
+            reclaim_terminal(shell, status == sys::WaitStatus::Signaled(sys::SIGINT));
+            Ok(status.code())
+        }
+        Compound::While(..) | Compound::ArithFor(..) | Compound::For(..) => {
+            shell.loops += 1;
+            let status = run_loop(shell, compound);
+            shell.loops -= 1;
+            status
+        }
+        Compound::Case(word, items) => {
+            let value = match expand::expand_word(shell, &expand::expand_tilde(shell, word, false))
+            {
+                Ok(value) => value,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return Ok(1);
+                }
+            };
+            let mut status = 0;
+            let mut fall_through = false;
+            for (patterns, body, end) in items {
+                if !fall_through {
+                    let mut matched = false;
+                    for pattern in patterns {
+                        let pattern = expand::expand_tilde(shell, pattern, false);
+                        match expand::expand_pattern(shell, &pattern) {
+                            Ok(pattern)
+                                if Pattern::new(&pattern, shell.shopt("extglob"))
+                                    .matches(&value) =>
+                            {
+                                matched = true;
+                                break;
+                            }
+                            Ok(_) => {}
+                            Err(err) => {
+                                eprintln!("{}", err);
+                                return Ok(1);
+                            }
+                        }
+                    }
+                    if !matched {
+                        continue;
+                    }
+                }
+                status = run_list(shell, body)?;
+                if shell.flow.is_some() {
+                    return Ok(status);
+                }
+                match end {
+                    CaseEnd::Break => return Ok(status),
+                    CaseEnd::FallThrough => fall_through = true,
+                    CaseEnd::TestNext => fall_through = false,
+                }
+            }
+            Ok(status)
+        }
+    }
+}
+
+// `None` when an operand couldn't be expanded or isn't a valid number or regular
+// expression, after reporting why
+fn test_cond(shell: &mut Shell, expr: &CondExpr) -> Option<bool> {
+    let expand = |shell: &mut Shell, word: &Word| {
+        let word = expand::expand_tilde(shell, word, false);
+        expand::expand_word(shell, &word)
+            .map_err(|err| eprintln!("{}", err))
+            .ok()
+    };
+    Some(match expr {
+        CondExpr::Word(word) => !expand(shell, word)?.is_empty(),
+        CondExpr::Unary(op, word) => {
+            let arg = expand(shell, word)?;
+            cond::unary(shell, op, &arg)
+        }
+        CondExpr::Binary(left, op, right) => {
+            let left = expand(shell, left)?;
+            match op.as_str() {
+                // the right side is a pattern, with the extglob forms always on
+                "=" | "==" | "!=" => {
+                    let right = expand::expand_tilde(shell, right, false);
+                    let pattern = expand::expand_pattern(shell, &right)
+                        .map_err(|err| eprintln!("{}", err))
+                        .ok()?;
+                    Pattern::new(&pattern, true).matches(&left) == (op != "!=")
+                }
+                "=~" => {
+                    let regex = expand::expand_regex(shell, right)
+                        .map_err(|err| eprintln!("{}", err))
+                        .ok()?;
+                    let groups = sys::regex_match(&regex, &left).ok()?;
+                    let matched = groups.is_some();
+                    let _ = shell
+                        .vars
+                        .set_array("BASH_REMATCH", groups.unwrap_or_default());
+                    matched
+                }
+                // both sides are arithmetic expressions
+                op if cond::is_numeric(op) => {
+                    let right = expand(shell, right)?;
+                    let mut eval = |expr: &str| {
+                        arith::eval(shell, expr)
+                            .map_err(|err| eprintln!("{}", err))
+                            .ok()
+                    };
+                    let left = eval(&left)?;
+                    cond::compare(left, op, eval(&right)?)
+                }
+                op => cond::binary(&left, op, &expand(shell, right)?),
+            }
+        }
+        CondExpr::Not(expr) => !test_cond(shell, expr)?,
+        CondExpr::And(left, right) => test_cond(shell, left)? && test_cond(shell, right)?,
+        CondExpr::Or(left, right) => test_cond(shell, left)? || test_cond(shell, right)?,
+        CondExpr::Group(expr) => test_cond(shell, expr)?,
+    })
+}
+
+// `shell.loops` counts this loop while it runs
+fn run_loop(shell: &mut Shell, compound: &Compound) -> io::Result<i32> {
+    match compound {
+        Compound::While(until, condition, body) => {
+            let mut status = 0;
+            loop {
+                let test = tested(shell, |shell| run_list(shell, condition))?;
+                match loop_control(shell) {
+                    Some(true) => break,
+                    Some(false) => continue,
+                    None => {}
+                }
+                if (test == 0) == *until {
+                    break;
+                }
+                status = run_list(shell, body)?;
+                if loop_control(shell) == Some(true) {
+                    break;
+                }
+            }
+            Ok(status)
+        }
+        Compound::ArithFor([init, condition, step], body) => {
+            let eval = |shell: &mut Shell, expr: &Word| match expand::eval_arith(shell, expr) {
+                Ok(value) => Some(value),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    None
+                }
+            };
+            let mut status = 0;
+            if eval(shell, init).is_none() {
+                return Ok(1);
+            }
+            loop {
+                match eval(shell, condition) {
+                    Some(0) => return Ok(status),
+                    Some(_) => {}
+                    None => return Ok(1),
+                }
+                status = run_list(shell, body)?;
+                if loop_control(shell) == Some(true) {
+                    return Ok(status);
+                }
+                if eval(shell, step).is_none() {
+                    return Ok(1);
+                }
+            }
+        }
+        Compound::For(name, words, body) => {
+            let values = match words {
+                Some(words) => match expand::expand_words(shell, words) {
+                    Ok(values) => values,
+                    Err(err) => return Ok(expand_failed(shell, err)),
+                },
+                None => shell.args.clone(),
+            };
+            let mut status = 0;
+            for value in values {
+                if let Err(err) = shell.vars.set(name, value) {
+                    eprintln!("{}", err);
+                    return Ok(1);
+                }
+                status = run_list(shell, body)?;
+                if loop_control(shell) == Some(true) {
+                    break;
+                }
+            }
+            Ok(status)
+        }
+        _ => unreachable!("not a loop"),
+    }
+}
+
+// takes the part of a pending `break` or `continue` that is meant for the innermost loop,
+// `Some(true)` when that loop has to stop
+fn loop_control(shell: &mut Shell) -> Option<bool> {
+    match shell.flow? {
+        Flow::Break(1) => {
+            shell.flow = None;
+            Some(true)
+        }
+        Flow::Continue(1) => {
+            shell.flow = None;
+            Some(false)
+        }
+        Flow::Break(count) => {
+            shell.flow = Some(Flow::Break(count - 1));
+            Some(true)
+        }
+        Flow::Continue(count) => {
+            shell.flow = Some(Flow::Continue(count - 1));
+            Some(true)
+        }
+        Flow::Return | Flow::Interrupt => Some(true),
+    }
+}
+
+// runs every command in a forked copy of the shell, with each one's stdout piped into the
+// next one's stdin, and returns the status of the last
+fn run_pipeline(shell: &mut Shell, pipeline: &Pipeline) -> io::Result<i32> {
+    // the commands failing is what `!` expects, so it's a test of their status
+    if pipeline.negated {
+        let status = tested(shell, |shell| run_piped(shell, pipeline))?;
+        return Ok((status == 0) as i32);
+    }
+    run_piped(shell, pipeline)
+}
+
+fn run_piped(shell: &mut Shell, pipeline: &Pipeline) -> io::Result<i32> {
+    if let [(command, _)] = pipeline.commands.as_slice() {
+        let status = run(shell, command)?;
+        let _ = shell.vars.set_array("PIPESTATUS", vec![status.to_string()]);
+        return Ok(status);
+    }
+    let count = pipeline.commands.len();
+    let mut pids = Vec::with_capacity(count);
+    let mut input: Option<OwnedFd> = None;
+    for (i, (command, pipe_stderr)) in pipeline.commands.iter().enumerate() {
+        let pipe = match i + 1 < count {
+            true => Some(sys::pipe()?),
+            false => None,
+        };
+        let pid = sys::fork()?;
+        if pid == 0 {
+            foreground_group(shell, 0, pids.first().copied().unwrap_or(sys::getpid()));
+            let mut dup = || -> io::Result<()> {
+                if let Some(read) = input.take() {
+                    sys::dup2(read.as_raw_fd(), 0)?;
+                }
+                if let Some((_, write)) = &pipe {
+                    sys::dup2(write.as_raw_fd(), 1)?;
+                    if *pipe_stderr {
+                        sys::dup2(write.as_raw_fd(), 2)?;
+                    }
+                }
+                Ok(())
+            };
+            let status = match dup() {
+                Ok(()) => {
+                    drop(pipe);
+                    shell.subshell();
+                    run(shell, command).unwrap_or(1)
+                }
+                Err(_) => 1,
+            };
+            sys::exit_child(status);
+        }
+        foreground_group(shell, pid, pids.first().copied().unwrap_or(pid));
+        pids.push(pid);
+        input = pipe.map(|(read, _)| read);
+    }
+    let mut statuses = Vec::with_capacity(count);
+    let mut interrupted = false;
+    for pid in pids {
+        let status = sys::waitpid(pid, 0)?;
+        interrupted |= status == sys::WaitStatus::Signaled(sys::SIGINT);
+        statuses.push(status.code());
+    }
+    reclaim_terminal(shell, interrupted);
+    let _ = shell.vars.set_array(
+        "PIPESTATUS",
+        statuses.iter().map(|status| status.to_string()).collect(),
+    );
+    // under `set -o pipefail` the last stage to fail decides the status
+    let status = match shell.option("pipefail") {
+        true => statuses.iter().rev().find(|status| **status != 0),
+        false => statuses.last(),
+    };
+    Ok(status.copied().unwrap_or_default())
+}
+
+// reports a word that couldn't be expanded, an unbound variable under `set -u` ending a
+// shell that isn't interactive
+fn expand_failed(shell: &mut Shell, err: expand::ExpandError) -> i32 {
+    eprintln!("{}", err);
+    if matches!(err, expand::ExpandError::Unbound(_)) && !shell.interactive {
+        exit(shell, Some(1));
+    }
+    1
+}
+
+// runs a simple command, in the background as a job when `background` has its text
+fn run_command(
+    shell: &mut Shell,
+    command: &SimpleCommand,
+    background: Option<&str>,
+) -> io::Result<i32> {
+    let (redirect_path, mut words) =
+        match get_redirect_path(shell, &command.words, &command.heredocs) {
+            Ok(v) => v,
+            Err(err) => return Ok(expand_failed(shell, err)),
+        };
+    let assigns = words
+        .iter()
+        .take_while(|v| v.assignment().is_some())
+        .count();
+    let mut env = Vec::with_capacity(assigns);
+    shell.subst_status = None;
+    for word in words.drain(..assigns) {
+        let (name, value) = word.assignment().unwrap();
+        let value = expand::expand_tilde(shell, &value, true);
+        match expand::expand_word(shell, &value) {
+            Ok(value) => {
+                trace(shell, &format!("{}={}", name, vars::single_quote(&value)));
+                env.push((name.to_owned(), value));
+            }
+            Err(err) => return Ok(expand_failed(shell, err)),
+        }
+    }
+    if words.is_empty() {
+        let mut status = shell.subst_status.unwrap_or_default();
+        for (name, value) in env {
+            if let Err(err) = shell.vars.set(&name, value) {
+                eprintln!("{}", err);
+                status = 1;
+            }
+        }
+        return Ok(status);
+    }
+    if let Some((name, _)) = env.iter().find(|(name, _)| shell.vars.is_readonly(name)) {
+        eprintln!("{}", vars::ReadonlyError(name.clone()));
+        return Ok(1);
+    }
+    let args = match expand::expand_words(shell, &words) {
+        Ok(args) => args,
+        Err(err) => return Ok(expand_failed(shell, err)),
+    };
+    if args.is_empty() {
+        return Ok(0);
+    }
+    let words = args.iter().map(|arg| vars::single_quote(arg));
+    trace(shell, &words.collect::<Vec<_>>().join(" "));
+    let function = (shell.functions.get(&args[0]).cloned()).map(|body| (body, args[1..].to_vec()));
+    let cmd = Cmd::from(args.into_iter().map(Cow::Owned).collect::<Vec<_>>());
+    // a builtin or function sees `NAME=value` prefixes as variables until it returns
+    let mut saved = Vec::new();
+    if function.is_some() || cmd.is_builtin() {
+        for (name, value) in &env {
+            saved.push((name, shell.vars.get(name).map(str::to_owned)));
+            let _ = shell.vars.set(name, value.clone());
+        }
+    }
+    let status = match (function, background) {
+        (Some((body, args)), Some(text)) => spawn_job(shell, text.to_owned(), |shell| {
+            call_function(shell, &body, args, redirect_path)
+        }),
+        (Some((body, args)), None) => call_function(shell, &body, args, redirect_path),
+        (None, Some(text)) => cmd.spawn_background(shell, redirect_path, &env, text),
+        (None, None) => cmd.execute(shell, redirect_path, &env),
+    };
+    for (name, value) in saved.into_iter().rev() {
+        let _ = match value {
+            Some(value) => shell.vars.set(name, value),
+            None => shell.vars.unset(name),
+        };
+    }
+    status
+}
+
+// under `set -x` prints a command as it runs, after the expanded `$PS4`
+fn trace(shell: &mut Shell, line: &str) {
+    if !shell.option("xtrace") {
+        return;
+    }
+    let ps4 = shell.vars.get("PS4").unwrap_or("+ ").to_owned();
+    let prefix = expand::expand_word(shell, &lexer::unsplit(&ps4)).unwrap_or(ps4);
+    eprintln!("{}{}", prefix, line);
+}
+
+// reports finished jobs, catches up with the shared history and gives the text to prompt
+// for a new command with
+fn prompt(shell: &mut Shell) -> io::Result<String> {
+    shell.jobs.notify(&mut io::stderr())?;
+    // picks up the commands run in other shells sharing the history file, a file that
+    // can't be read was already reported when the history was loaded
+    if let Some(path) = shell.vars.get("HISTFILE") {
+        let _ = shell.history.sync(path);
+        let size = history_size(shell, "HISTSIZE");
+        shell.history.limit(size);
+    }
+    // `PROMPT_COMMAND` and then a `precmd` function run before each prompt, which can change
+    // `PS1`, and leave `$?` as the last command set it
+    let status = shell.last_status;
+    if let Some(command) = shell.vars.get("PROMPT_COMMAND").map(str::to_owned) {
+        run_line(shell, &command)?;
+    }
+    if shell.functions.contains_key("precmd") {
+        run_line(shell, "precmd")?;
+    }
+    shell.last_status = status;
+    let ps1 = shell.vars.get("PS1").unwrap_or("$ ").to_owned();
+    Ok(decode_prompt(shell, &ps1))
+}
+
+// `ps1` with its backslash escapes replaced, `\[` and `\]` around text that takes up no room
+// on the screen become the \x01 and \x02 the line editor leaves out of the prompt's width
+fn decode_prompt(shell: &Shell, ps1: &str) -> String {
+    let mut out = String::new();
+    let mut chars = ps1.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let Some(c) = chars.next() else {
+            out.push('\\');
+            break;
+        };
+        match c {
+            'u' => {
+                let user = sys::user_name().or_else(|| shell.vars.get("USER").map(str::to_owned));
+                out.push_str(&user.unwrap_or_default());
+            }
+            'h' | 'H' => {
+                let host = sys::host_name().unwrap_or_default();
+                match c {
+                    'h' => out.push_str(host.split('.').next().unwrap_or_default()),
+                    _ => out.push_str(&host),
+                }
+            }
+            'w' | 'W' => {
+                let pwd = std::env::current_dir().unwrap_or_default();
+                let pwd = pwd.to_string_lossy();
+                let home = shell.vars.get("HOME").filter(|home| !home.is_empty());
+                let pwd = match home.and_then(|home| pwd.strip_prefix(home)) {
+                    Some("") => "~".to_owned(),
+                    Some(rest) if c == 'w' && rest.starts_with('/') => format!("~{}", rest),
+                    _ => pwd.into_owned(),
+                };
+                match c {
+                    'W' if pwd != "/" => out.push_str(pwd.rsplit('/').next().unwrap_or_default()),
+                    _ => out.push_str(&pwd),
+                }
+            }
+            '$' => out.push(if sys::euid() == 0 { '#' } else { '$' }),
+            't' | 'd' => {
+                let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
+                let format = if c == 't' { "%H:%M:%S" } else { "%a %b %d" };
+                out.push_str(&sys::format_time(
+                    format,
+                    now.map_or(0, |now| now.as_secs()),
+                ));
+            }
+            's' => out.push_str(shell.name.rsplit('/').next().unwrap_or_default()),
+            'n' => out.push('\n'),
+            'e' => out.push('\x1b'),
+            '\\' => out.push('\\'),
+            '[' => out.push('\x01'),
+            ']' => out.push('\x02'),
+            c => {
+                out.push('\\');
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
 #[derive(Debug, PartialEq, Eq)]
 enum Cmd<'a> {
-    Exit(i32),
+    Exit(Option<i32>),
     Echo(Vec<Cow<'a, str>>),
     Type(Cow<'a, str>),
     Pwd,
     Cd(Cow<'a, str>),
+    Kill(Vec<Cow<'a, str>>),
+    Wait(Vec<Cow<'a, str>>),
+    Disown(Vec<Cow<'a, str>>),
+    Export(Vec<Cow<'a, str>>),
+    Unset(Vec<Cow<'a, str>>),
+    Readonly(Vec<Cow<'a, str>>),
+    Shopt(Vec<Cow<'a, str>>),
+    Set(Vec<Cow<'a, str>>),
+    Exec(Vec<Cow<'a, str>>),
+    Read(Vec<Cow<'a, str>>),
+    Local(Vec<Cow<'a, str>>),
+    Return(Vec<Cow<'a, str>>),
+    Shift(Vec<Cow<'a, str>>),
+    Getopts(Vec<Cow<'a, str>>),
+    Trap(Vec<Cow<'a, str>>),
+    History(Vec<Cow<'a, str>>),
+    Fc(Vec<Cow<'a, str>>),
+    Complete(Vec<Cow<'a, str>>),
+    Test(Vec<Cow<'a, str>>),
+    Let(Vec<Cow<'a, str>>),
+    Eval(Vec<Cow<'a, str>>),
+    // `source` and `.`, with the name it was called by
+    Source(Cow<'a, str>, Vec<Cow<'a, str>>),
+    // `[`, the same as `test` but ended by `]`
+    Bracket(Vec<Cow<'a, str>>),
+    Break(Vec<Cow<'a, str>>),
+    Continue(Vec<Cow<'a, str>>),
     Other(Cow<'a, str>, Vec<Cow<'a, str>>),
 }
 
@@ -43,13 +1243,32 @@ impl fmt::Display for Cmd<'_> {
             Self::Type(_) => f.write_str("type")?,
             Self::Pwd => f.write_str("pwd")?,
             Self::Cd(_) => f.write_str("cd")?,
-            Self::Other(cmd, _) => {
-                if let Some(path) = find_path(cmd) {
-                    return write!(f, "{} is {}", cmd, path);
-                } else {
-                    return write!(f, "{}: not found", cmd);
-                }
-            }
+            Self::Kill(_) => f.write_str("kill")?,
+            Self::Wait(_) => f.write_str("wait")?,
+            Self::Disown(_) => f.write_str("disown")?,
+            Self::Export(_) => f.write_str("export")?,
+            Self::Unset(_) => f.write_str("unset")?,
+            Self::Readonly(_) => f.write_str("readonly")?,
+            Self::Shopt(_) => f.write_str("shopt")?,
+            Self::Set(_) => f.write_str("set")?,
+            Self::Exec(_) => f.write_str("exec")?,
+            Self::Read(_) => f.write_str("read")?,
+            Self::Local(_) => f.write_str("local")?,
+            Self::Return(_) => f.write_str("return")?,
+            Self::Shift(_) => f.write_str("shift")?,
+            Self::Getopts(_) => f.write_str("getopts")?,
+            Self::Trap(_) => f.write_str("trap")?,
+            Self::History(_) => f.write_str("history")?,
+            Self::Fc(_) => f.write_str("fc")?,
+            Self::Complete(_) => f.write_str("complete")?,
+            Self::Test(_) => f.write_str("test")?,
+            Self::Let(_) => f.write_str("let")?,
+            Self::Eval(_) => f.write_str("eval")?,
+            Self::Source(name, _) => f.write_str(name)?,
+            Self::Bracket(_) => f.write_str("[")?,
+            Self::Break(_) => f.write_str("break")?,
+            Self::Continue(_) => f.write_str("continue")?,
+            Self::Other(cmd, _) => return write!(f, "{}", cmd),
         };
         f.write_str(" is a shell builtin")
     }
@@ -62,12 +1281,30 @@ impl Cmd<'_> {
 }
 
 impl<'a> Cmd<'a> {
-    #[allow(unused)]
-    fn execute(&'a self, out: Redirection<'_>) -> io::Result<()> {
-        let mut stdout = BufWriter::new(out.stdout()?);
-        let mut stderr = BufWriter::new(out.stderr()?);
-        match self {
-            Self::Exit(code) => std::process::exit(*code),
+    // `env` holds the `NAME=value` prefixes that only apply to this command
+    fn execute(
+        &'a self,
+        shell: &mut Shell,
+        out: Redirection<'_>,
+        env: &[(String, String)],
+    ) -> io::Result<i32> {
+        let (fds, out_file, err_file) = match out.open().and_then(|fds| {
+            let (out_file, err_file) = (fds.file(1)?, fds.file(2)?);
+            Ok((fds, out_file, err_file))
+        }) {
+            Ok(opened) => opened,
+            Err(err) => {
+                eprintln!("{}", err);
+                return Ok(1);
+            }
+        };
+        let mut stdout = BufWriter::new(&out_file);
+        let mut stderr = BufWriter::new(&err_file);
+        let status = match self {
+            Self::Exit(code) => {
+                shell.jobs.hangup();
+                exit(shell, *code)
+            }
             Self::Echo(args) => {
                 let mut iter = args.iter();
                 if let Some(arg) = iter.next() {
@@ -77,68 +1314,171 @@ impl<'a> Cmd<'a> {
                     write!(stdout, " {}", arg)?;
                 }
                 writeln!(stdout)?;
+                0
             }
+            Self::Type(arg) if arg.is_empty() => 0,
             Self::Type(arg) => {
                 let arg = match arg {
                     Cow::Owned(v) => v,
                     Cow::Borrowed(v) => *v,
                 };
+                if let Some(body) = shell.functions.get(arg) {
+                    writeln!(stdout, "{} is a function", arg)?;
+                    writeln!(stdout, "{} () {}", arg, body)?;
+                    return Ok(0);
+                }
                 let cmd = Self::from(arg);
                 if cmd.is_builtin() {
                     writeln!(stdout, "{}", cmd)?;
-                    return Ok(());
+                    return Ok(0);
                 }
-                if let Some(v) = find_path(arg) {
+                if let Some(v) = find_path(shell, arg) {
                     writeln!(stdout, "{} is {}", arg, v)?;
-                    return Ok(());
+                    return Ok(0);
                 }
                 writeln!(stdout, "{}: not found", arg)?;
+                1
             }
             Self::Pwd => {
                 let pwd = std::env::current_dir()?;
                 writeln!(stdout, "{}", pwd.to_string_lossy())?;
+                0
             }
-            Self::Cd(path) => {
-                if *path == "~" {
-                    let home = std::env::var("HOME").unwrap();
-                    std::env::set_current_dir(home)?;
-                } else if std::env::set_current_dir(PathBuf::from_str(path).unwrap()).is_err() {
-                    writeln!(stdout, "cd: {}: No such file or directory", path)?;
-                }
+            Self::Cd(path) => builtins::cd(shell, path, &mut stdout, &mut stderr)?,
+            Self::Kill(args) => builtins::kill(shell, args, &mut stdout, &mut stderr)?,
+            Self::Wait(args) => builtins::wait(shell, args, &mut stderr)?,
+            Self::Disown(args) => builtins::disown(shell, args, &mut stderr)?,
+            Self::Export(args) => builtins::export(shell, args, &mut stdout, &mut stderr)?,
+            Self::Unset(args) => builtins::unset(shell, args, &mut stderr)?,
+            Self::Readonly(args) => builtins::readonly(shell, args, &mut stdout, &mut stderr)?,
+            Self::Shopt(args) => builtins::shopt(shell, args, &mut stdout, &mut stderr)?,
+            Self::Set(args) => builtins::set(shell, args, &mut stdout, &mut stderr)?,
+            Self::Read(args) => {
+                let mut stdin = fds.file(0)?;
+                builtins::read(shell, args, &mut stdin, &mut stderr)?
             }
+            Self::Local(args) => builtins::local(shell, args, &mut stdout, &mut stderr)?,
+            Self::Return(args) => builtins::return_(shell, args, &mut stderr)?,
+            Self::Shift(args) => builtins::shift(shell, args, &mut stderr)?,
+            Self::Getopts(args) => builtins::getopts(shell, args, &mut stderr)?,
+            Self::Trap(args) => builtins::trap(shell, args, &mut stdout, &mut stderr)?,
+            Self::History(args) => builtins::history(shell, args, &mut stdout, &mut stderr)?,
+            Self::Complete(args) => builtins::complete(shell, args, &mut stdout, &mut stderr)?,
+            Self::Test(args) => builtins::test(shell, "test", args, &mut stderr)?,
+            Self::Let(args) => builtins::let_(shell, args, &mut stderr)?,
+            // the redirections apply to everything the arguments run
+            Self::Eval(args) => {
+                let saved = fds.save()?;
+                fds.persist()?;
+                let status = run_line(shell, &args.join(" "));
+                restore(saved)?;
+                status?
+            }
+            Self::Fc(args) => {
+                let saved = fds.save()?;
+                fds.persist()?;
+                let status = fc(shell, args);
+                restore(saved)?;
+                status?
+            }
+            Self::Source(_, args) => match args.split_first() {
+                None => {
+                    writeln!(stderr, "source: filename argument required")?;
+                    writeln!(stderr, "source: usage: source filename [arguments]")?;
+                    2
+                }
+                Some((name, args)) => {
+                    let saved = fds.save()?;
+                    fds.persist()?;
+                    let status = source(shell, name, args);
+                    restore(saved)?;
+                    status?
+                }
+            },
+            Self::Bracket(args) => builtins::test(shell, "[", args, &mut stderr)?,
+            Self::Break(args) => builtins::loop_control(shell, "break", args, &mut stderr)?,
+            Self::Continue(args) => builtins::loop_control(shell, "continue", args, &mut stderr)?,
+            Self::Exec(args) => match args.split_first() {
+                None => {
+                    stdout.flush()?;
+                    stderr.flush()?;
+                    match fds.persist() {
+                        Ok(()) => 0,
+                        Err(err) => {
+                            writeln!(stderr, "exec: {}", builtins::os_error(&err))?;
+                            1
+                        }
+                    }
+                }
+                Some((cmd, _)) if find_path(shell, cmd).is_none() => {
+                    writeln!(stderr, "exec: {}: not found", cmd)?;
+                    127
+                }
+                Some((cmd, args)) => {
+                    io::stdout().flush()?;
+                    // only returns if the command couldn't be started
+                    let err = external(shell, cmd, args, env, &fds, false)?.exec();
+                    writeln!(stderr, "exec: {}: {}", cmd, builtins::os_error(&err))?;
+                    126
+                }
+            },
             Self::Other(cmd, args) => {
-                if find_path(cmd).is_some() {
-                    let mut child = process::Command::new(cmd.as_ref())
-                        .args(args.iter().map(|v| v.as_ref()).collect::<Vec<&str>>())
-                        .stdout(Stdio::from(out.stdout()?))
-                        .stderr(Stdio::from(out.stderr()?))
-                        .spawn()?;
-                    let _ = child.wait()?;
+                if find_path(shell, cmd).is_some() {
+                    let mut child = external(shell, cmd, args, env, &fds, true)?.spawn()?;
+                    let pid = child.id() as i32;
+                    foreground_group(shell, pid, pid);
+                    let status = child.wait()?;
+                    reclaim_terminal(shell, status.signal() == Some(sys::SIGINT));
+                    exit_code(status)
                 } else {
                     writeln!(stdout, "{}: command not found", cmd)?;
+                    127
                 }
             }
+        };
+        Ok(status)
+    }
+
+    fn spawn_background(
+        &'a self,
+        shell: &mut Shell,
+        out: Redirection<'_>,
+        env: &[(String, String)],
+        text: &str,
+    ) -> io::Result<i32> {
+        let Self::Other(cmd, args) = self else {
+            return self.execute(shell, out, env);
+        };
+        if find_path(shell, cmd).is_none() {
+            return self.execute(shell, out, env);
         }
-        Ok(())
+        let fds = match out.open() {
+            Ok(fds) => fds,
+            Err(err) => {
+                eprintln!("{}", err);
+                return Ok(1);
+            }
+        };
+        let child = external(shell, cmd, args, env, &fds, false)?
+            .process_group(0)
+            .spawn()?;
+        let id = shell.jobs.push(child.id() as i32, text.to_owned());
+        shell.last_background = Some(child.id() as i32);
+        if shell.interactive {
+            eprintln!("[{}] {}", id, child.id());
+        }
+        Ok(0)
     }
 }
 
 impl<'a> From<&'a str> for Cmd<'a> {
     fn from(value: &'a str) -> Self {
-        let value = value.trim_start();
-        let mut cmd_args = IterArgs::new(value);
-        let cmd = cmd_args.next().unwrap();
-        match cmd.as_ref() {
-            "exit" => {
-                let code = cmd_args.next().unwrap_or_default();
-                Self::Exit(code.parse().unwrap_or_default())
-            }
-            "echo" => Self::Echo(cmd_args.collect()),
-            "type" => Self::Type(cmd_args.next().unwrap_or_default()),
-            "pwd" => Self::Pwd,
-            "cd" => Self::Cd(cmd_args.next().unwrap_or(Cow::Borrowed("~"))),
-            _ => Self::Other(cmd, cmd_args.collect()),
-        }
+        Self::from(
+            value
+                .split_whitespace()
+                .map(Cow::Borrowed)
+                .collect::<Vec<_>>(),
+        )
     }
 }
 impl<'a> From<Vec<Cow<'a, str>>> for Cmd<'a> {
@@ -146,271 +1486,485 @@ impl<'a> From<Vec<Cow<'a, str>>> for Cmd<'a> {
         let mut iter = value.into_iter();
         let cmd = iter.next().unwrap();
         match cmd.as_ref() {
-            "exit" => {
-                let code = iter.next().unwrap_or_default();
-                Self::Exit(code.parse().unwrap_or_default())
-            }
+            "exit" => Self::Exit(iter.next().map(|code| code.parse().unwrap_or_default())),
             "echo" => Self::Echo(iter.collect()),
             "type" => Self::Type(iter.next().unwrap_or_default()),
             "pwd" => Self::Pwd,
             "cd" => Self::Cd(iter.next().unwrap_or(Cow::Borrowed("~"))),
+            "kill" => Self::Kill(iter.collect()),
+            "wait" => Self::Wait(iter.collect()),
+            "disown" => Self::Disown(iter.collect()),
+            "export" => Self::Export(iter.collect()),
+            "unset" => Self::Unset(iter.collect()),
+            "readonly" => Self::Readonly(iter.collect()),
+            "shopt" => Self::Shopt(iter.collect()),
+            "set" => Self::Set(iter.collect()),
+            "exec" => Self::Exec(iter.collect()),
+            "read" => Self::Read(iter.collect()),
+            "local" => Self::Local(iter.collect()),
+            "return" => Self::Return(iter.collect()),
+            "shift" => Self::Shift(iter.collect()),
+            "getopts" => Self::Getopts(iter.collect()),
+            "trap" => Self::Trap(iter.collect()),
+            "history" => Self::History(iter.collect()),
+            "fc" => Self::Fc(iter.collect()),
+            "complete" => Self::Complete(iter.collect()),
+            "test" => Self::Test(iter.collect()),
+            "let" => Self::Let(iter.collect()),
+            "eval" => Self::Eval(iter.collect()),
+            "source" | "." => Self::Source(cmd, iter.collect()),
+            "[" => Self::Bracket(iter.collect()),
+            "break" => Self::Break(iter.collect()),
+            "continue" => Self::Continue(iter.collect()),
             _ => Self::Other(cmd, iter.collect()),
         }
     }
 }
-fn find_path<T: AsRef<str>>(value: T) -> Option<String> {
-    let env = std::env::var("PATH").unwrap();
-    for path in env.split(':') {
-        for entry in fs::read_dir(path).ok()? {
-            let dir = entry.ok()?;
-            let file = dir.file_name();
-            let name = file.to_string_lossy();
-            if name == *value.as_ref() {
-                return Some(dir.path().to_string_lossy().to_string());
-            }
+
+// an external command with its arguments, environment and redirections
+fn external(
+    shell: &Shell,
+    cmd: &str,
+    args: &[Cow<'_, str>],
+    env: &[(String, String)],
+    fds: &Fds,
+    foreground: bool,
+) -> io::Result<process::Command> {
+    let mut command = process::Command::new(cmd);
+    command
+        .args(args.iter().map(|v| v.as_ref()))
+        .env_clear()
+        .envs(shell.vars.exported())
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(fds.stdio(0)?)
+        .stdout(fds.stdio(1)?)
+        .stderr(fds.stdio(2)?);
+    fds.install(&mut command);
+    let signals = shell.unshielded_signals();
+    let job_control = foreground && shell.interactive;
+    if job_control {
+        command.process_group(0);
+    }
+    if job_control || !signals.is_empty() {
+        unsafe {
+            command.pre_exec(move || {
+                // while SIGTTOU is still ignored
+                if job_control {
+                    let _ = sys::tcsetpgrp(0, sys::getpid());
+                }
+                for sig in &signals {
+                    sys::default_signal(*sig);
+                }
+                Ok(())
+            });
         }
     }
-    None
+    Ok(command)
 }
 
-struct IterArgs<'a> {
-    whole: &'a str,
-    start: usize,
-}
-
-impl<'a> Iterator for IterArgs<'a> {
-    type Item = Cow<'a, str>;
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            if self.start >= self.whole.len() {
-                return None;
-            }
-            let input = &self.whole[self.start..];
-            let mut end = 0;
-            let mut rm = Vec::new();
-            handle_args(&mut input.chars().enumerate().peekable(), &mut rm, &mut end);
-            let got_str = remove_unwanted(&input[0..end], rm);
-            self.start += end;
-            if got_str.is_empty() && end >= self.whole.len() {
-                return None;
-            }
-            if got_str.is_empty() {
-                continue;
-            }
-            return Some(got_str);
-        }
+// with job control a foreground job runs in a process group of its own, led by its first
+// process, and gets the terminal; both sides of the fork call this so neither has to wait
+fn foreground_group(shell: &Shell, pid: i32, leader: i32) {
+    if shell.interactive {
+        let _ = sys::setpgid(pid, leader);
+        let _ = sys::tcsetpgrp(0, leader);
     }
 }
-impl<'a> IterArgs<'a> {
-    fn new(value: &'a str) -> Self {
-        Self {
-            whole: value,
-            start: 0,
+
+// takes the terminal back from a finished foreground job, Ctrl-C only reached the job so
+// it counts as the shell getting it too
+fn reclaim_terminal(shell: &Shell, interrupted: bool) {
+    if shell.interactive {
+        let _ = sys::tcsetpgrp(0, sys::getpgrp());
+        if interrupted {
+            sys::mark_caught(sys::SIGINT);
         }
     }
 }
 
-// BUG: in some input it return Owned value, when it should be Borrowed
-fn remove_unwanted(value: &str, remove: Vec<usize>) -> Cow<'_, str> {
-    if remove.is_empty() || value.is_empty() {
-        return Cow::Borrowed(value);
-    }
-    let mut start = 0;
-    for i in remove.iter() {
-        if *i != start {
-            break;
-        }
-        start += 1;
-    }
-    let mut end = value.len() - 1;
-    for item in remove.iter().rev() {
-        if *item != end {
-            break;
-        }
-        end -= 1;
-    }
-    if start + (value.len() - 1 - end) >= value.len() {
-        return Cow::Borrowed("");
-    }
-    if start + (value.len() - 1 - end) >= remove.len() {
-        return Cow::Borrowed(&value[start..end + 1]);
-    }
-    let mut st = String::with_capacity(end - start + 1);
-    let mut remove_iter = remove[start..].iter();
-    let mut current_remove = remove_iter.next();
-    for (index, c) in value[start..end + 1].chars().enumerate() {
-        match current_remove {
-            Some(remove_index) if *remove_index == index + start => {
-                current_remove = remove_iter.next();
-            }
-            _ => st.push(c),
-        }
-    }
-    Cow::Owned(st)
+fn exit_code(status: process::ExitStatus) -> i32 {
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or_default())
 }
-fn handle_args(iter: &mut Peekable<Enumerate<Chars>>, remove: &mut Vec<usize>, end: &mut usize) {
-    if iter.peek().is_none() {
-        return;
+
+// where the command `value` is run from, a name with a `/` in it is already a path
+fn find_path<T: AsRef<str>>(shell: &Shell, value: T) -> Option<String> {
+    let name = value.as_ref();
+    if name.contains('/') {
+        let meta = fs::metadata(name).ok()?;
+        return complete::is_executable(&meta).then(|| name.to_owned());
     }
-    let mut i = 0;
-    while let Some((index, c)) = iter.next() {
-        i = index;
-        match c {
-            ' ' | '\t' | '\r' => {
-                remove.push(index);
-                *end = index + 1;
-                return;
-            }
-            '\\' => {
-                remove.push(index);
-                iter.next();
-                i += 1;
-            }
-            '"' => {
-                remove.push(index);
-                while let Some((ii, v)) = iter.next() {
-                    i = ii;
-                    match v {
-                        '"' => {
-                            remove.push(ii);
-                            break;
-                        }
-                        '\\' => {
-                            if let Some((_, v)) = iter.peek() {
-                                if matches!(*v, '\\' | '"') {
-                                    remove.push(ii);
-                                    iter.next();
-                                }
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            '\'' => {
-                remove.push(index);
-                for (ii, v) in iter.by_ref() {
-                    i = ii;
-                    if v == '\'' {
-                        remove.push(ii);
-                        break;
-                    }
-                }
+    let env = shell.vars.get("PATH").unwrap_or_default();
+    for path in env.split(':') {
+        // a directory on PATH that can't be read is skipped
+        let Ok(entries) = fs::read_dir(path) else {
+            continue;
+        };
+        for dir in entries.flatten() {
+            let file = dir.file_name();
+            let name = file.to_string_lossy();
+            if name == *value.as_ref() {
+                return Some(dir.path().to_string_lossy().to_string());
             }
-            _ => {}
         }
     }
-    *end = i + 1;
+    None
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum RedirOps {
+    Read,
     Redirect,
     Append,
+    // `>` under `set -o noclobber`, which won't truncate an existing regular file
+    NoClobber,
+}
+
+// what a redirection points its descriptor at
+#[derive(Debug)]
+enum Target<'a> {
+    Path(Cow<'a, str>),
+    // the expanded body of a here-document or here-string
+    Text(String),
+    // `n>&m` and `n<&m`
+    Dup(i32),
+    // `n>&-` and `n<&-`
+    Close,
 }
 
 #[derive(Debug)]
 struct RedirectPath<'a> {
-    path: Cow<'a, str>,
+    fd: i32,
+    target: Target<'a>,
     ops: RedirOps,
 }
 
-impl RedirectPath<'_> {
-    fn default_stdout() -> Self {
-        Self {
-            path: Cow::Borrowed("/dev/stdout"),
-            ops: RedirOps::Append,
+fn open_path(path: &str, ops: &RedirOps) -> io::Result<fs::File> {
+    let file = match ops {
+        RedirOps::Read => fs::File::open(path),
+        RedirOps::Append => fs::OpenOptions::new().append(true).create(true).open(path),
+        RedirOps::Redirect => fs::File::create(path),
+        RedirOps::NoClobber => {
+            if fs::metadata(path).is_ok_and(|meta| meta.is_file()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{}: cannot overwrite existing file", path),
+                ));
+            }
+            fs::File::create(path)
         }
+    };
+    file.map_err(|err| open_error(path, err))
+}
+
+// Linux's default pipe capacity
+const PIPE_CAPACITY: usize = 65536;
+
+// descriptors opened for a redirection are moved up here, clear of the ones scripts name
+const FD_FLOOR: i32 = 10;
+
+// names the file in the error, the way it is reported before the command runs
+fn open_error(path: &str, err: io::Error) -> io::Error {
+    io::Error::new(
+        err.kind(),
+        format!("{}: {}", path, builtins::os_error(&err)),
+    )
+}
+
+fn bad_fd(fd: i32) -> io::Error {
+    io::Error::other(format!("{}: Bad file descriptor", fd))
+}
+
+// a pipe with `text` waiting to be read
+fn text_pipe(text: &str) -> io::Result<OwnedFd> {
+    let (read, write) = sys::pipe()?;
+    let mut write = fs::File::from(write);
+    if text.len() <= PIPE_CAPACITY {
+        // fits without blocking, and is there even if `exec` replaces the shell
+        write.write_all(text.as_bytes())?;
+    } else {
+        // written from another thread so a body larger than the pipe buffer
+        // can't block the shell before the command starts reading
+        let text = text.to_owned();
+        std::thread::spawn(move || write.write_all(text.as_bytes()));
+    }
+    Ok(read)
+}
+
+// a copy of one of the shell's own descriptors
+fn inherited(fd: i32) -> io::Result<OwnedFd> {
+    match sys::is_open(fd) {
+        true => sys::dup_above(fd, FD_FLOOR),
+        false => Err(bad_fd(fd)),
+    }
+}
+
+// moves `fd` up to `FD_FLOOR` so `exec` can't replace a descriptor the shell still holds
+fn lift(fd: impl Into<OwnedFd>) -> io::Result<OwnedFd> {
+    let fd = fd.into();
+    match fd.as_raw_fd() < FD_FLOOR {
+        true => sys::dup_above(fd.as_raw_fd(), FD_FLOOR),
+        false => Ok(fd),
     }
-    fn default_stderr() -> Self {
-        RedirectPath {
-            path: Cow::Borrowed("/dev/stderr"),
-            ops: RedirOps::Append,
+}
+
+// puts back the descriptors `Fds::save` copied
+fn restore(saved: Vec<(i32, Option<OwnedFd>)>) -> io::Result<()> {
+    io::stdout().flush()?;
+    for (n, fd) in saved {
+        match fd {
+            Some(fd) => sys::dup2(fd.as_raw_fd(), n)?,
+            None => {
+                let _ = sys::close(n);
+            }
         }
     }
+    Ok(())
 }
 
-#[derive(Debug)]
+// builtins read and write a closed descriptor as `/dev/null`
+fn null(fd: i32) -> io::Result<fs::File> {
+    let ops = if fd == 0 {
+        RedirOps::Read
+    } else {
+        RedirOps::Append
+    };
+    Ok(lift(open_path("/dev/null", &ops)?)?.into())
+}
+
+// the redirections of one command, applied in order
+#[derive(Debug, Default)]
 struct Redirection<'a> {
-    std_out: RedirectPath<'a>,
-    std_err: RedirectPath<'a>,
+    redirects: Vec<RedirectPath<'a>>,
 }
 
-impl Default for Redirection<'_> {
-    fn default() -> Self {
-        Self {
-            std_out: RedirectPath::default_stdout(),
-            std_err: RedirectPath::default_stderr(),
+impl Redirection<'_> {
+    fn open(&self) -> io::Result<Fds> {
+        let mut fds = Fds::default();
+        for redirect in &self.redirects {
+            let fd = match &redirect.target {
+                Target::Path(path) => Some(Rc::new(lift(open_path(path, &redirect.ops)?)?)),
+                Target::Text(text) => Some(Rc::new(lift(text_pipe(text)?)?)),
+                Target::Dup(from) => match fds.table.get(from) {
+                    Some(Some(fd)) => Some(fd.clone()),
+                    Some(None) => return Err(bad_fd(*from)),
+                    None => Some(Rc::new(inherited(*from)?)),
+                },
+                Target::Close => None,
+            };
+            fds.table.insert(redirect.fd, fd);
         }
+        Ok(fds)
     }
 }
 
-impl Redirection<'_> {
-    fn stdout(&self) -> io::Result<fs::File> {
-        match self.std_out.ops {
-            RedirOps::Append => Ok(fs::OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open(self.std_out.path.as_ref())?),
-            RedirOps::Redirect => Ok(fs::File::create(self.std_out.path.as_ref())?),
+// the descriptors a command's redirections changed, `None` for a closed one
+#[derive(Debug, Default)]
+struct Fds {
+    table: BTreeMap<i32, Option<Rc<OwnedFd>>>,
+}
+
+impl Fds {
+    // the child of an external command shares the shell's stdio unless it is redirected
+    fn stdio(&self, fd: i32) -> io::Result<Stdio> {
+        match self.table.get(&fd) {
+            Some(Some(fd)) => Ok(sys::dup_above(fd.as_raw_fd(), FD_FLOOR)?.into()),
+            _ => Ok(Stdio::inherit()),
+        }
+    }
+
+    // where a builtin reads stdin or writes stdout and stderr, a pipe in a pipeline is
+    // already the shell's own descriptor
+    fn file(&self, n: i32) -> io::Result<fs::File> {
+        let fd = match self.table.get(&n) {
+            Some(Some(fd)) => sys::dup_above(fd.as_raw_fd(), FD_FLOOR)?,
+            Some(None) => return null(n),
+            None if !sys::is_open(n) => return null(n),
+            None => inherited(n)?,
+        };
+        Ok(fd.into())
+    }
+
+    // hands the descriptors above stderr to the child of an external command and closes the
+    // closed ones, which it then sees as unopened rather than reading or writing `/dev/null`
+    fn install(&self, command: &mut process::Command) {
+        let fds: Vec<_> = self
+            .table
+            .iter()
+            .filter(|(n, fd)| **n > 2 || fd.is_none())
+            .map(|(n, fd)| (*n, fd.as_ref().map(|fd| fd.as_raw_fd())))
+            .collect();
+        if fds.is_empty() {
+            return;
         }
+        unsafe {
+            command.pre_exec(move || {
+                for (n, fd) in &fds {
+                    match fd {
+                        Some(fd) => sys::dup2(*fd, *n)?,
+                        None => {
+                            let _ = sys::close(*n);
+                        }
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+
+    // copies of the shell's descriptors that `persist` is about to replace
+    fn save(&self) -> io::Result<Vec<(i32, Option<OwnedFd>)>> {
+        let mut saved = Vec::with_capacity(self.table.len());
+        for n in self.table.keys() {
+            let fd = match sys::is_open(*n) {
+                true => Some(sys::dup_above(*n, FD_FLOOR)?),
+                false => None,
+            };
+            saved.push((*n, fd));
+        }
+        Ok(saved)
     }
-    fn stderr(&self) -> io::Result<fs::File> {
-        match self.std_err.ops {
-            RedirOps::Append => Ok(fs::OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open(self.std_err.path.as_ref())?),
-            RedirOps::Redirect => Ok(fs::File::create(self.std_err.path.as_ref())?),
+
+    // keeps the redirections for the rest of the session, as `exec` without a command does
+    fn persist(&self) -> io::Result<()> {
+        io::stdout().flush()?;
+        for (n, fd) in &self.table {
+            match fd {
+                Some(fd) => {
+                    sys::dup2(fd.as_raw_fd(), *n)?;
+                }
+                None => {
+                    let _ = sys::close(*n);
+                }
+            }
         }
+        Ok(())
     }
 }
 
-fn get_redirect_path(args: Vec<Cow<'_, str>>) -> io::Result<(Redirection<'_>, Vec<Cow<'_, str>>)> {
+// `heredocs` holds the bodies for the `<<` operators in `args`, in order
+fn get_redirect_path(
+    shell: &mut Shell,
+    args: &[Word],
+    heredocs: &[Word],
+) -> Result<(Redirection<'static>, Vec<Word>), expand::ExpandError> {
     let mut args1 = Vec::with_capacity(args.len());
-    let mut iter = args.into_iter();
-    let mut stdout_path = None;
-    let mut stdout_ops = RedirOps::Append;
-    let mut stderr_path = None;
-    let mut stderr_ops = RedirOps::Append;
+    let mut iter = args.iter().cloned();
+    let mut heredocs = heredocs.iter();
+    let mut redirects = Vec::new();
+    // `>|` truncates even under `set -o noclobber`
+    let noclobber = shell.option("noclobber");
+    let truncate = |op: &str| match noclobber && !op.ends_with('|') {
+        true => RedirOps::NoClobber,
+        false => RedirOps::Redirect,
+    };
     while let Some(arg) = iter.next() {
-        match arg.as_ref() {
-            ">" | "1>" => {
-                if stdout_path.is_none() {
-                    stdout_path = iter.next();
-                    stdout_ops = RedirOps::Redirect;
+        // `2>` and the like, the lexer keeps the descriptor with the operator
+        let literal = arg.literal().unwrap_or_default();
+        let (number, op) = literal.split_at(
+            literal.len()
+                - literal
+                    .trim_start_matches(|c: char| c.is_ascii_digit())
+                    .len(),
+        );
+        let fd = number.parse::<i32>().ok();
+        let (fd, target, ops) = match op {
+            "<" => match iter.next() {
+                Some(word) => (
+                    fd.unwrap_or(0),
+                    Target::Path(redirect_target(shell, &word)?),
+                    RedirOps::Read,
+                ),
+                None => continue,
+            },
+            "<<<" => match iter.next() {
+                Some(word) => {
+                    let word = expand::expand_tilde(shell, &word, false);
+                    let text = expand::expand_word(shell, &word)? + "\n";
+                    (fd.unwrap_or(0), Target::Text(text), RedirOps::Read)
                 }
-            }
-            ">>" | "1>>" => {
-                if stderr_path.is_none() {
-                    stdout_path = iter.next();
+                None => continue,
+            },
+            "<<" | "<<-" => {
+                iter.next();
+                match heredocs.next() {
+                    Some(body) => (
+                        fd.unwrap_or(0),
+                        Target::Text(expand::expand_word(shell, body)?),
+                        RedirOps::Read,
+                    ),
+                    None => continue,
                 }
             }
-            "2>" => {
-                if stderr_path.is_none() {
-                    stderr_path = iter.next();
-                    stderr_ops = RedirOps::Redirect;
+            ">" | ">|" | ">>" => match iter.next() {
+                Some(word) => {
+                    let ops = match op {
+                        ">>" => RedirOps::Append,
+                        _ => truncate(op),
+                    };
+                    (
+                        fd.unwrap_or(1),
+                        Target::Path(redirect_target(shell, &word)?),
+                        ops,
+                    )
                 }
-            }
-            "2>>" => {
-                if stderr_path.is_none() {
-                    stderr_path = iter.next();
+                None => continue,
+            },
+            "&>" | "&>>" | ">&" | "<&" => {
+                let Some(word) = iter.next() else {
+                    continue;
+                };
+                let path = redirect_target(shell, &word)?;
+                let target = match path.as_ref() {
+                    _ if op.starts_with('&') => None,
+                    "-" => Some(Target::Close),
+                    v if is_fd(v) => Some(Target::Dup(v.parse().unwrap_or(i32::MAX))),
+                    // `>&file` is an older spelling of `&>file`
+                    _ if op == ">&" && fd.is_none() => None,
+                    _ => return Err(expand::ExpandError::AmbiguousRedirect(word.unquoted())),
+                };
+                match target {
+                    Some(target) => {
+                        let default = if op == "<&" { 0 } else { 1 };
+                        (fd.unwrap_or(default), target, RedirOps::Append)
+                    }
+                    None => {
+                        let ops = match op {
+                            "&>>" => RedirOps::Append,
+                            _ => truncate(op),
+                        };
+                        redirects.push(RedirectPath {
+                            fd: 1,
+                            target: Target::Path(path),
+                            ops,
+                        });
+                        (2, Target::Dup(1), RedirOps::Append)
+                    }
                 }
             }
-            _ => args1.push(arg),
-        }
+            _ => {
+                args1.push(arg);
+                continue;
+            }
+        };
+        redirects.push(RedirectPath { fd, target, ops });
+    }
+    Ok((Redirection { redirects }, args1))
+}
+
+fn is_fd(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_digit())
+}
+
+// a target has to expand to exactly one field
+fn redirect_target(
+    shell: &mut Shell,
+    word: &Word,
+) -> Result<Cow<'static, str>, expand::ExpandError> {
+    let mut fields = expand::expand_words(shell, std::slice::from_ref(word))?;
+    match fields.len() {
+        1 => Ok(Cow::Owned(fields.remove(0))),
+        _ => Err(expand::ExpandError::AmbiguousRedirect(word.unquoted())),
     }
-    Ok((
-        Redirection {
-            std_out: RedirectPath {
-                path: stdout_path.unwrap_or(Cow::Borrowed("/dev/stdout")),
-                ops: stdout_ops,
-            },
-            std_err: RedirectPath {
-                path: stderr_path.unwrap_or(Cow::Borrowed("/dev/stderr")),
-                ops: stderr_ops,
-            },
-        },
-        args1,
-    ))
 }
@@ -0,0 +1,764 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::rc::Rc;
+use std::vec;
+
+use crate::cond;
+use crate::lexer::{Lexer, Word, WordPart};
+use crate::vars;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error("syntax error near unexpected token `{0}'")]
+    Unexpected(String),
+    #[error("`{0}': not a valid identifier")]
+    InvalidName(String),
+    // the input stopped inside a command, more lines can still complete it
+    #[error("syntax error: unexpected end of file")]
+    Incomplete,
+}
+
+// the operators that end a simple command
+const CONTROL: &[&str] = &[
+    "\n", ";", "&", "|", "|&", "&&", "||", "(", ")", ";;", ";&", ";;&", "((", "))",
+];
+
+// reserved words that can only continue a compound command, never start one
+const CLOSING: &[&str] = &["then", "elif", "else", "fi", "do", "done", "esac", "}"];
+
+// the operators that end a `case` item
+const CASE_ENDS: &[&str] = &[";;", ";&", ";;&", "esac"];
+
+// commands separated by `;`, `&` or newlines
+pub type List = Vec<Item>;
+
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub and_or: AndOr,
+    // ended with `&`
+    pub background: bool,
+}
+
+// pipelines joined by `&&` and `||`
+#[derive(Debug, Clone)]
+pub struct AndOr {
+    pub first: Pipeline,
+    // `true` for `&&`, `false` for `||`
+    pub rest: Vec<(bool, Pipeline)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    // after a `!`, which inverts the status
+    pub negated: bool,
+    // each command with whether `|&` sends its stderr down the pipe too
+    pub commands: Vec<(Command, bool)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Simple(SimpleCommand),
+    // the words after a compound command can only be redirections
+    Compound(Compound, SimpleCommand),
+    // `name () body`, which only defines the function when it runs
+    Function(String, Rc<Command>),
+    // `coproc name command`, the name defaults to `COPROC`
+    Coproc(String, Box<Command>),
+}
+
+#[derive(Debug, Clone)]
+pub enum Compound {
+    // the `if` and `elif` conditions with their bodies, then the `else` body
+    If(Vec<(List, List)>, Option<List>),
+    // `until` when the flag is set, which loops while the condition fails
+    While(bool, List, List),
+    // the words after `in`, `None` without them loops over the positional parameters
+    For(String, Option<Vec<Word>>, List),
+    // `for ((init; condition; step))`, an empty expression is left out
+    ArithFor([Word; 3], List),
+    // the word and each item's patterns, body and terminator
+    Case(Word, Vec<(Vec<Word>, List, CaseEnd)>),
+    // `{ list; }`
+    Group(List),
+    // `( list )`, run by a copy of the shell
+    Subshell(List),
+    // `[[ expression ]]`
+    Cond(CondExpr),
+    // `(( expression ))`
+    Arith(Word),
+}
+
+#[derive(Debug, Clone)]
+pub enum CondExpr {
+    Unary(String, Word),
+    Binary(Word, String, Word),
+    // true when the word isn't empty
+    Word(Word),
+    Not(Box<CondExpr>),
+    And(Box<CondExpr>, Box<CondExpr>),
+    Or(Box<CondExpr>, Box<CondExpr>),
+    // `( expression )`
+    Group(Box<CondExpr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseEnd {
+    // `;;` and the last item before `esac`
+    Break,
+    // `;&` runs the next body without testing its patterns
+    FallThrough,
+    // `;;&` goes on testing the next item's patterns
+    TestNext,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimpleCommand {
+    pub words: Vec<Word>,
+    // the bodies of the here-documents this command's `<<` operators read
+    pub heredocs: Vec<Word>,
+}
+
+pub fn parse(input: &str) -> Result<List, ParseError> {
+    let mut lexer = Lexer::new(input);
+    let words = lexer.by_ref().collect::<Vec<_>>();
+    if lexer.is_incomplete() {
+        return Err(ParseError::Incomplete);
+    }
+    let mut parser = Parser {
+        words: words.into_iter().peekable(),
+        heredocs: lexer.heredocs().into_iter(),
+    };
+    let list = parser.list(&[])?;
+    match parser.words.next() {
+        Some(word) => Err(unexpected(&word)),
+        None => Ok(list),
+    }
+}
+
+// the `;` separated expressions of `for ((...))`
+fn split_exprs(expr: &Word) -> Vec<Word> {
+    let mut exprs = vec![Word::default()];
+    for part in &expr.0 {
+        let WordPart::Literal(text) = part else {
+            exprs.last_mut().unwrap().0.push(part.clone());
+            continue;
+        };
+        for (i, text) in text.split(';').enumerate() {
+            if i > 0 {
+                exprs.push(Word::default());
+            }
+            if !text.is_empty() {
+                exprs
+                    .last_mut()
+                    .unwrap()
+                    .0
+                    .push(WordPart::Literal(text.to_owned()));
+            }
+        }
+    }
+    exprs
+}
+
+// the reserved words and operators that start a compound command
+fn is_compound(word: &str) -> bool {
+    matches!(
+        word,
+        "{" | "(" | "((" | "[[" | "if" | "while" | "until" | "for" | "case"
+    )
+}
+
+fn is_control(word: &Word) -> bool {
+    matches!(word.literal(), Some(op) if CONTROL.contains(&op))
+}
+
+fn unexpected(word: &Word) -> ParseError {
+    match word.literal() {
+        Some("\n") => ParseError::Unexpected(String::from("newline")),
+        _ => ParseError::Unexpected(word.to_string()),
+    }
+}
+
+struct Parser {
+    words: Peekable<vec::IntoIter<Word>>,
+    heredocs: vec::IntoIter<Word>,
+}
+
+impl Parser {
+    fn peek(&mut self) -> Option<&str> {
+        self.words.peek().and_then(Word::literal)
+    }
+
+    fn linebreak(&mut self) {
+        while self.words.next_if(|v| v.literal() == Some("\n")).is_some() {}
+    }
+
+    // consumes the reserved word `word`, which has to come next
+    fn expect(&mut self, word: &str) -> Result<(), ParseError> {
+        match self.words.next() {
+            Some(next) if next.literal() == Some(word) => Ok(()),
+            Some(next) => Err(unexpected(&next)),
+            None => Err(ParseError::Incomplete),
+        }
+    }
+
+    // stops before any of the reserved words in `ends`, the whole input when it is empty
+    fn list(&mut self, ends: &[&str]) -> Result<List, ParseError> {
+        let mut list = Vec::new();
+        loop {
+            self.linebreak();
+            if self.words.peek().is_none() {
+                return match ends.is_empty() {
+                    true => Ok(list),
+                    false => Err(ParseError::Incomplete),
+                };
+            }
+            if self.peek().is_some_and(|word| ends.contains(&word)) {
+                return Ok(list);
+            }
+            let and_or = self.and_or()?;
+            let background = self.peek() == Some("&");
+            list.push(Item { and_or, background });
+            match self.peek() {
+                Some(";" | "\n" | "&") => {
+                    self.words.next();
+                }
+                // a reserved word can follow a compound command directly
+                Some(word) if ends.contains(&word) => return Ok(list),
+                _ => {
+                    if let Some(word) = self.words.peek() {
+                        return Err(unexpected(word));
+                    }
+                }
+            }
+        }
+    }
+
+    fn and_or(&mut self) -> Result<AndOr, ParseError> {
+        let first = self.pipeline()?;
+        let mut rest = Vec::new();
+        while let Some(op @ ("&&" | "||")) = self.peek() {
+            let and = op == "&&";
+            self.words.next();
+            self.linebreak();
+            rest.push((and, self.pipeline()?));
+        }
+        Ok(AndOr { first, rest })
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let negated = self.words.next_if(|v| v.literal() == Some("!")).is_some();
+        let mut commands = Vec::new();
+        loop {
+            let command = self.command()?;
+            let pipe_stderr = match self.peek() {
+                Some(op @ ("|" | "|&")) => op == "|&",
+                _ => {
+                    commands.push((command, false));
+                    return Ok(Pipeline { negated, commands });
+                }
+            };
+            self.words.next();
+            self.linebreak();
+            commands.push((command, pipe_stderr));
+        }
+    }
+
+    fn command(&mut self) -> Result<Command, ParseError> {
+        let compound = match self.peek() {
+            Some("if") => self.if_clause()?,
+            Some(word @ ("while" | "until")) => {
+                let until = word == "until";
+                self.words.next();
+                let condition = self.condition(&["do"])?;
+                Compound::While(until, condition, self.do_group()?)
+            }
+            Some("for") => self.for_clause()?,
+            Some("case") => self.case_clause()?,
+            Some("{") => self.brace_group()?,
+            Some("((") => {
+                self.words.next();
+                let expr = self.words.next().ok_or(ParseError::Incomplete)?;
+                self.expect("))")?;
+                Compound::Arith(expr)
+            }
+            Some("[[") => {
+                self.words.next();
+                let expr = self.cond_or()?;
+                self.expect("]]")?;
+                Compound::Cond(expr)
+            }
+            Some("(") => {
+                self.words.next();
+                let list = self.condition(&[")"])?;
+                self.expect(")")?;
+                Compound::Subshell(list)
+            }
+            Some("coproc") => {
+                self.words.next();
+                let mut name = String::from("COPROC");
+                if !self.peek().is_some_and(is_compound) {
+                    let word = match self.words.next() {
+                        Some(word) if is_control(&word) => return Err(unexpected(&word)),
+                        Some(word) => word,
+                        None => return Err(ParseError::Incomplete),
+                    };
+                    // only a compound command can follow a name
+                    match word.literal() {
+                        Some(literal)
+                            if vars::is_name(literal) && self.peek().is_some_and(is_compound) =>
+                        {
+                            name = literal.to_owned();
+                        }
+                        _ => {
+                            let mut command = SimpleCommand::default();
+                            self.push_word(&mut command, word);
+                            self.simple_words(&mut command);
+                            return Ok(Command::Coproc(name, Box::new(Command::Simple(command))));
+                        }
+                    }
+                }
+                return Ok(Command::Coproc(name, Box::new(self.command()?)));
+            }
+            Some("function") => {
+                self.words.next();
+                let name = match self.words.next() {
+                    Some(word) if is_control(&word) => return Err(unexpected(&word)),
+                    Some(word) => word.to_string(),
+                    None => return Err(ParseError::Incomplete),
+                };
+                if self.words.next_if(|v| v.literal() == Some("(")).is_some() {
+                    self.expect(")")?;
+                }
+                return Ok(Command::Function(name, Rc::new(self.function_body()?)));
+            }
+            Some(word) if CLOSING.contains(&word) => {
+                return Err(unexpected(self.words.peek().unwrap()))
+            }
+            _ => {
+                let command = self.simple_command()?;
+                // `name ()` starts a function definition
+                if let ([word], Some("(")) = (command.words.as_slice(), self.peek()) {
+                    match word.literal() {
+                        Some(name) if word.assignment().is_none() => {
+                            let name = name.to_owned();
+                            self.words.next();
+                            self.expect(")")?;
+                            let body = self.function_body()?;
+                            return Ok(Command::Function(name, Rc::new(body)));
+                        }
+                        _ => return Err(unexpected(self.words.peek().unwrap())),
+                    }
+                }
+                return Ok(Command::Simple(command));
+            }
+        };
+        Ok(Command::Compound(compound, self.redirections()?))
+    }
+
+    // a compound command with its redirections
+    fn function_body(&mut self) -> Result<Command, ParseError> {
+        self.linebreak();
+        match self.peek() {
+            Some(word) if is_compound(word) => self.command(),
+            _ => Err(match self.words.peek() {
+                Some(word) => unexpected(word),
+                None => ParseError::Incomplete,
+            }),
+        }
+    }
+
+    fn cond_or(&mut self) -> Result<CondExpr, ParseError> {
+        let mut expr = self.cond_and()?;
+        while self.peek() == Some("||") {
+            self.words.next();
+            expr = CondExpr::Or(Box::new(expr), Box::new(self.cond_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn cond_and(&mut self) -> Result<CondExpr, ParseError> {
+        let mut expr = self.cond_not()?;
+        while self.peek() == Some("&&") {
+            self.words.next();
+            expr = CondExpr::And(Box::new(expr), Box::new(self.cond_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn cond_not(&mut self) -> Result<CondExpr, ParseError> {
+        self.linebreak();
+        if self.words.next_if(|v| v.literal() == Some("!")).is_some() {
+            return Ok(CondExpr::Not(Box::new(self.cond_not()?)));
+        }
+        if self.words.next_if(|v| v.literal() == Some("(")).is_some() {
+            let expr = self.cond_or()?;
+            self.linebreak();
+            self.expect(")")?;
+            return Ok(CondExpr::Group(Box::new(expr)));
+        }
+        let word = self.cond_word()?;
+        let op = word.literal().unwrap_or_default();
+        let ends = |word: &str| matches!(word, "]]" | "&&" | "||" | ")" | "\n");
+        if cond::UNARY.contains(&op) && !self.peek().is_some_and(ends) {
+            return Ok(CondExpr::Unary(op.to_owned(), self.cond_word()?));
+        }
+        match self.peek() {
+            Some(op) if op == "=~" || cond::BINARY.contains(&op) => {
+                let op = op.to_owned();
+                self.words.next();
+                Ok(CondExpr::Binary(word, op, self.cond_word()?))
+            }
+            _ => Ok(CondExpr::Word(word)),
+        }
+    }
+
+    // an operand inside `[[ ]]`, where `<` and `>` compare strings
+    fn cond_word(&mut self) -> Result<Word, ParseError> {
+        match self.words.next() {
+            Some(word) if matches!(word.literal(), Some("<" | ">")) => Ok(word),
+            Some(word) if is_control(&word) || word.literal() == Some("]]") => {
+                Err(unexpected(&word))
+            }
+            Some(word) => Ok(word),
+            None => Err(ParseError::Incomplete),
+        }
+    }
+
+    fn brace_group(&mut self) -> Result<Compound, ParseError> {
+        self.words.next();
+        let list = self.condition(&["}"])?;
+        self.expect("}")?;
+        Ok(Compound::Group(list))
+    }
+
+    fn for_clause(&mut self) -> Result<Compound, ParseError> {
+        self.words.next();
+        if self.peek() == Some("((") {
+            self.words.next();
+            let expr = self.words.next().ok_or(ParseError::Incomplete)?;
+            self.expect("))")?;
+            let Ok(exprs) = <[Word; 3]>::try_from(split_exprs(&expr)) else {
+                return Err(ParseError::Unexpected(format!("(({}))", expr)));
+            };
+            self.words
+                .next_if(|v| matches!(v.literal(), Some(";" | "\n")));
+            self.linebreak();
+            return Ok(Compound::ArithFor(exprs, self.do_group()?));
+        }
+        let name = match self.words.next() {
+            Some(word) if word.literal().is_some_and(vars::is_name) => word.to_string(),
+            Some(word) if is_control(&word) => return Err(unexpected(&word)),
+            Some(word) => return Err(ParseError::InvalidName(word.to_string())),
+            None => return Err(ParseError::Incomplete),
+        };
+        self.linebreak();
+        let mut words = None;
+        if self.peek() == Some("in") {
+            self.words.next();
+            let mut list = Vec::new();
+            while let Some(word) = self.words.next_if(|v| !is_control(v)) {
+                list.push(word);
+            }
+            words = Some(list);
+        }
+        self.words
+            .next_if(|v| matches!(v.literal(), Some(";" | "\n")));
+        self.linebreak();
+        Ok(Compound::For(name, words, self.do_group()?))
+    }
+
+    fn case_clause(&mut self) -> Result<Compound, ParseError> {
+        self.words.next();
+        let word = match self.words.next() {
+            Some(word) if is_control(&word) => return Err(unexpected(&word)),
+            Some(word) => word,
+            None => return Err(ParseError::Incomplete),
+        };
+        self.linebreak();
+        self.expect("in")?;
+        let mut items = Vec::new();
+        loop {
+            self.linebreak();
+            if self.peek() == Some("esac") {
+                self.words.next();
+                return Ok(Compound::Case(word, items));
+            }
+            self.words.next_if(|v| v.literal() == Some("("));
+            let mut patterns = Vec::new();
+            loop {
+                match self.words.next() {
+                    Some(word) if is_control(&word) => return Err(unexpected(&word)),
+                    Some(word) => patterns.push(word),
+                    None => return Err(ParseError::Incomplete),
+                }
+                if self.words.next_if(|v| v.literal() == Some("|")).is_none() {
+                    break;
+                }
+            }
+            self.expect(")")?;
+            let body = self.list(CASE_ENDS)?;
+            let end = match self.words.next().as_ref().and_then(Word::literal) {
+                Some(";&") => CaseEnd::FallThrough,
+                Some(";;&") => CaseEnd::TestNext,
+                Some("esac") => {
+                    items.push((patterns, body, CaseEnd::Break));
+                    return Ok(Compound::Case(word, items));
+                }
+                _ => CaseEnd::Break,
+            };
+            items.push((patterns, body, end));
+        }
+    }
+
+    // `do list done`
+    fn do_group(&mut self) -> Result<List, ParseError> {
+        self.expect("do")?;
+        let body = self.condition(&["done"])?;
+        self.expect("done")?;
+        Ok(body)
+    }
+
+    fn redirections(&mut self) -> Result<SimpleCommand, ParseError> {
+        let mut redirections = SimpleCommand::default();
+        while let Some(op) = self.peek() {
+            let op = op.trim_start_matches(|c: char| c.is_ascii_digit());
+            if !op.starts_with(['<', '>']) && !op.starts_with("&>") {
+                break;
+            }
+            if matches!(op, "<<" | "<<-") {
+                redirections.heredocs.extend(self.heredocs.next());
+            }
+            redirections.words.extend(self.words.next());
+            match self.words.next_if(|v| !is_control(v)) {
+                Some(target) => redirections.words.push(target),
+                None => {
+                    return Err(match self.words.peek() {
+                        Some(word) => unexpected(word),
+                        None => ParseError::Incomplete,
+                    })
+                }
+            }
+        }
+        Ok(redirections)
+    }
+
+    fn if_clause(&mut self) -> Result<Compound, ParseError> {
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        self.words.next();
+        loop {
+            let condition = self.condition(&["then"])?;
+            self.expect("then")?;
+            let body = self.condition(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            match self.words.next().as_ref().and_then(Word::literal) {
+                Some("elif") => continue,
+                Some("else") => {
+                    otherwise = Some(self.condition(&["fi"])?);
+                    self.expect("fi")?;
+                }
+                _ => {}
+            }
+            return Ok(Compound::If(branches, otherwise));
+        }
+    }
+
+    // a list that can't be empty
+    fn condition(&mut self, ends: &[&str]) -> Result<List, ParseError> {
+        let list = self.list(ends)?;
+        match (list.is_empty(), self.words.peek()) {
+            (true, Some(word)) => Err(unexpected(word)),
+            _ => Ok(list),
+        }
+    }
+
+    fn simple_command(&mut self) -> Result<SimpleCommand, ParseError> {
+        let mut command = SimpleCommand::default();
+        self.simple_words(&mut command);
+        if command.words.is_empty() {
+            return Err(match self.words.peek() {
+                Some(word) => unexpected(word),
+                None => ParseError::Incomplete,
+            });
+        }
+        Ok(command)
+    }
+
+    fn simple_words(&mut self, command: &mut SimpleCommand) {
+        while let Some(word) = self.words.next_if(|v| !is_control(v)) {
+            self.push_word(command, word);
+        }
+    }
+
+    fn push_word(&mut self, command: &mut SimpleCommand, word: Word) {
+        let op = word.literal().unwrap_or_default();
+        if matches!(
+            op.trim_start_matches(|c: char| c.is_ascii_digit()),
+            "<<" | "<<-"
+        ) {
+            command.heredocs.extend(self.heredocs.next());
+        }
+        command.words.push(word);
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.and_or)?;
+        if self.background {
+            f.write_str(" &")?;
+        }
+        Ok(())
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, list: &List) -> fmt::Result {
+    for (i, item) in list.iter().enumerate() {
+        if i > 0 {
+            f.write_str(" ")?;
+        }
+        write!(f, "{}", item)?;
+        if !item.background {
+            f.write_str(";")?;
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for AndOr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (and, pipeline) in &self.rest {
+            write!(f, " {} {}", if *and { "&&" } else { "||" }, pipeline)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negated {
+            f.write_str("! ")?;
+        }
+        for (i, (command, pipe_stderr)) in self.commands.iter().enumerate() {
+            write!(f, "{}", command)?;
+            if i + 1 < self.commands.len() {
+                f.write_str(if *pipe_stderr { " |& " } else { " | " })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Simple(command) => write!(f, "{}", command),
+            Self::Compound(compound, redirections) if redirections.words.is_empty() => {
+                write!(f, "{}", compound)
+            }
+            Self::Compound(compound, redirections) => write!(f, "{} {}", compound, redirections),
+            Self::Function(name, body) => write!(f, "{} () {}", name, body),
+            Self::Coproc(name, command) => write!(f, "coproc {} {}", name, command),
+        }
+    }
+}
+
+impl fmt::Display for Compound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::If(branches, otherwise) => {
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    f.write_str(if i == 0 { "if " } else { " elif " })?;
+                    write_list(f, condition)?;
+                    f.write_str(" then ")?;
+                    write_list(f, body)?;
+                }
+                if let Some(body) = otherwise {
+                    f.write_str(" else ")?;
+                    write_list(f, body)?;
+                }
+                f.write_str(" fi")
+            }
+            Self::While(until, condition, body) => {
+                f.write_str(if *until { "until " } else { "while " })?;
+                write_list(f, condition)?;
+                f.write_str(" do ")?;
+                write_list(f, body)?;
+                f.write_str(" done")
+            }
+            Self::ArithFor([init, condition, step], body) => {
+                write!(f, "for (({}; {}; {})); do ", init, condition, step)?;
+                write_list(f, body)?;
+                f.write_str(" done")
+            }
+            Self::Group(list) => {
+                f.write_str("{ ")?;
+                write_list(f, list)?;
+                f.write_str(" }")
+            }
+            Self::Cond(expr) => write!(f, "[[ {} ]]", expr),
+            Self::Arith(expr) => write!(f, "(({}))", expr),
+            Self::Subshell(list) => {
+                f.write_str("( ")?;
+                write_list(f, list)?;
+                f.write_str(" )")
+            }
+            Self::Case(word, items) => {
+                write!(f, "case {} in", word)?;
+                for (patterns, body, end) in items {
+                    for (i, pattern) in patterns.iter().enumerate() {
+                        f.write_str(if i == 0 { " " } else { " | " })?;
+                        write!(f, "{}", pattern)?;
+                    }
+                    f.write_str(") ")?;
+                    write_list(f, body)?;
+                    f.write_str(match end {
+                        CaseEnd::Break => ";;",
+                        CaseEnd::FallThrough => ";&",
+                        CaseEnd::TestNext => ";;&",
+                    })?;
+                }
+                f.write_str(" esac")
+            }
+            Self::For(name, words, body) => {
+                write!(f, "for {}", name)?;
+                if let Some(words) = words {
+                    f.write_str(" in")?;
+                    for word in words {
+                        write!(f, " {}", word)?;
+                    }
+                }
+                f.write_str("; do ")?;
+                write_list(f, body)?;
+                f.write_str(" done")
+            }
+        }
+    }
+}
+
+impl fmt::Display for CondExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unary(op, word) => write!(f, "{} {}", op, word),
+            Self::Binary(left, op, right) => write!(f, "{} {} {}", left, op, right),
+            Self::Word(word) => write!(f, "{}", word),
+            Self::Not(expr) => write!(f, "! {}", expr),
+            Self::And(left, right) => write!(f, "{} && {}", left, right),
+            Self::Or(left, right) => write!(f, "{} || {}", left, right),
+            Self::Group(expr) => write!(f, "( {} )", expr),
+        }
+    }
+}
+
+impl fmt::Display for SimpleCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, word) in self.words.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", word)?;
+        }
+        Ok(())
+    }
+}
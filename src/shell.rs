@@ -0,0 +1,146 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+use crate::complete::Spec;
+use crate::history::History;
+use crate::jobs::Jobs;
+use crate::parser::Command;
+use crate::sys;
+use crate::vars::Vars;
+
+// the options `shopt` can toggle, all off by default
+pub const SHOPT_NAMES: &[&str] = &["dotglob", "extglob", "globstar", "nullglob"];
+
+// the options `set` can toggle by letter or with `-o name`, all off by default apart from
+// `histexpand` and `emacs` in an interactive shell
+pub const SET_OPTIONS: &[(Option<char>, &str)] = &[
+    (None, "emacs"),
+    (Some('e'), "errexit"),
+    (Some('C'), "noclobber"),
+    (Some('f'), "noglob"),
+    (Some('H'), "histexpand"),
+    (Some('u'), "nounset"),
+    (None, "pipefail"),
+    (None, "vi"),
+    (Some('x'), "xtrace"),
+];
+
+// the signals from the terminal an interactive shell ignores, its commands get them back
+pub const SHIELDED_SIGNALS: &[i32] = &[sys::SIGQUIT, sys::SIGTSTP, sys::SIGTTIN, sys::SIGTTOU];
+
+// the keys `traps` keeps the EXIT and ERR pseudo-signals under, next to real signals
+pub const EXIT_TRAP: i32 = 0;
+pub const ERR_TRAP: i32 = 65;
+
+// a jump out of the commands that are running, which each enclosing list and loop passes on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    // leave this many loops
+    Break(usize),
+    // leave one less than this many loops and start the next iteration of the last
+    Continue(usize),
+    // leave the running function
+    Return,
+    // leave everything, after Ctrl-C in an interactive shell
+    Interrupt,
+}
+
+#[derive(Debug, Default)]
+pub struct Shell {
+    pub jobs: Jobs,
+    pub history: History,
+    pub vars: Vars,
+    pub functions: BTreeMap<String, Rc<Command>>,
+    // how `complete` said to complete each command's arguments
+    pub completions: BTreeMap<String, Spec>,
+    // the command `trap` set for each signal, empty when it is ignored
+    pub traps: BTreeMap<i32, String>,
+    // `$0`, the script being run or the shell itself
+    pub name: String,
+    // the positional parameters, `$1` onwards
+    pub args: Vec<String>,
+    // reading commands from a terminal, which gets prompts and job notifications
+    pub interactive: bool,
+    pub last_status: i32,
+    // `$$`, the shell's process id, which subshells keep
+    pub pid: i32,
+    // `$!`, the process id of the last job started in the background
+    pub last_background: Option<i32>,
+    // status of the last command substitution, reported by lines that only assign
+    pub subst_status: Option<i32>,
+    // the `shopt` options that are on
+    pub shopts: BTreeSet<&'static str>,
+    // the `set` options that are on
+    pub options: BTreeSet<&'static str>,
+    pub flow: Option<Flow>,
+    // the `OPTIND` `getopts` last left and how far into that word it got
+    pub optpos: (usize, usize),
+    // how many loops the running command is inside
+    pub loops: usize,
+    // how many conditions the running command is inside, `set -e` ignores failures there
+    pub conditions: usize,
+    // how many files `source` is running, which `return` can leave
+    pub sourced: usize,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Self {
+            vars: Vars::from_env(),
+            pid: sys::getpid(),
+            ..Default::default()
+        }
+    }
+
+    pub fn shopt(&self, name: &str) -> bool {
+        self.shopts.contains(name)
+    }
+
+    pub fn option(&self, name: &str) -> bool {
+        self.options.contains(name)
+    }
+
+    // what `trap -` puts back, an interactive shell keeps catching Ctrl-C and resizes and
+    // ignoring the other terminal signals
+    pub fn reset_signal(&self, sig: i32) {
+        match self.interactive {
+            true if sig == sys::SIGINT || sig == sys::SIGWINCH => sys::catch_signal(sig),
+            true if SHIELDED_SIGNALS.contains(&sig) => sys::ignore_signal(sig),
+            _ => sys::default_signal(sig),
+        }
+    }
+
+    // the shielded signals a command run from here should get the default action for, the
+    // ones a trap ignores stay ignored
+    pub fn unshielded_signals(&self) -> Vec<i32> {
+        match self.interactive {
+            true => (SHIELDED_SIGNALS.iter())
+                .filter(|sig| !self.traps.contains_key(sig))
+                .copied()
+                .collect(),
+            false => Vec::new(),
+        }
+    }
+
+    // called in a forked child that runs part of this shell's input
+    pub fn subshell(&mut self) {
+        self.jobs = Jobs::default();
+        // traps don't carry over, though ignored signals stay ignored
+        self.traps.retain(|sig, command| {
+            if !command.is_empty() {
+                sys::default_signal(*sig);
+            }
+            command.is_empty()
+        });
+        // Ctrl-C stops a subshell of an interactive shell like any other command
+        if self.interactive && !self.traps.contains_key(&sys::SIGINT) {
+            sys::default_signal(sys::SIGINT);
+        }
+        for sig in self.unshielded_signals() {
+            sys::default_signal(sig);
+        }
+        self.interactive = false;
+        // a subshell writing into a closed pipe dies like any other command would
+        sys::default_signal(sys::SIGPIPE);
+    }
+}
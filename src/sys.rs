@@ -0,0 +1,558 @@
+use std::ffi::{c_char, CStr, CString};
+use std::io::{self, Write};
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+mod ffi {
+    use std::ffi::c_char;
+
+    // glibc's `struct passwd`
+    #[repr(C)]
+    pub struct Passwd {
+        pub pw_name: *const c_char,
+        pub pw_passwd: *const c_char,
+        pub pw_uid: u32,
+        pub pw_gid: u32,
+        pub pw_gecos: *const c_char,
+        pub pw_dir: *const c_char,
+        pub pw_shell: *const c_char,
+    }
+
+    // glibc's `regex_t`, only `re_nsub` (the number of groups) is read
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct Regex {
+        pub fields: [usize; 6],
+        pub re_nsub: usize,
+        pub flags: usize,
+    }
+
+    // glibc's `struct termios`
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct Termios {
+        pub c_iflag: u32,
+        pub c_oflag: u32,
+        pub c_cflag: u32,
+        pub c_lflag: u32,
+        pub c_line: u8,
+        pub c_cc: [u8; 32],
+        pub c_ispeed: u32,
+        pub c_ospeed: u32,
+    }
+
+    // glibc's `struct tm`
+    #[repr(C)]
+    pub struct Tm {
+        pub fields: [i32; 9],
+        pub tm_gmtoff: i64,
+        pub tm_zone: *const c_char,
+    }
+
+    // `struct winsize`
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct Winsize {
+        pub ws_row: u16,
+        pub ws_col: u16,
+        pub ws_xpixel: u16,
+        pub ws_ypixel: u16,
+    }
+
+    // `struct pollfd`
+    #[repr(C)]
+    pub struct PollFd {
+        pub fd: i32,
+        pub events: i16,
+        pub revents: i16,
+    }
+
+    // glibc's `regmatch_t`
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct RegMatch {
+        pub rm_so: i32,
+        pub rm_eo: i32,
+    }
+
+    extern "C" {
+        pub fn kill(pid: i32, sig: i32) -> i32;
+        pub fn waitpid(pid: i32, status: *mut i32, options: i32) -> i32;
+        pub fn fork() -> i32;
+        pub fn pipe2(fds: *mut i32, flags: i32) -> i32;
+        pub fn dup2(old: i32, new: i32) -> i32;
+        pub fn close(fd: i32) -> i32;
+        pub fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+        pub fn signal(sig: i32, handler: usize) -> usize;
+        pub fn _exit(status: i32) -> !;
+        pub fn setpgid(pid: i32, pgid: i32) -> i32;
+        pub fn getpid() -> i32;
+        pub fn getpgrp() -> i32;
+        pub fn tcsetpgrp(fd: i32, pgrp: i32) -> i32;
+        pub fn tcgetattr(fd: i32, termios: *mut Termios) -> i32;
+        pub fn tcsetattr(fd: i32, action: i32, termios: *const Termios) -> i32;
+        pub fn read(fd: i32, buf: *mut u8, count: usize) -> isize;
+        pub fn flock(fd: i32, operation: i32) -> i32;
+        pub fn localtime_r(time: *const i64, tm: *mut Tm) -> *mut Tm;
+        pub fn strftime(s: *mut c_char, max: usize, format: *const c_char, tm: *const Tm) -> usize;
+        pub fn ioctl(fd: i32, request: u64, ...) -> i32;
+        pub fn poll(fds: *mut PollFd, nfds: u64, timeout: i32) -> i32;
+        pub fn getpwnam(name: *const c_char) -> *const Passwd;
+        pub fn getpwuid(uid: u32) -> *const Passwd;
+        pub fn gethostname(name: *mut c_char, len: usize) -> i32;
+        pub fn access(path: *const c_char, mode: i32) -> i32;
+        pub fn isatty(fd: i32) -> i32;
+        pub fn geteuid() -> u32;
+        pub fn getegid() -> u32;
+        pub fn regcomp(preg: *mut Regex, pattern: *const c_char, cflags: i32) -> i32;
+        pub fn regexec(
+            preg: *const Regex,
+            string: *const c_char,
+            nmatch: usize,
+            pmatch: *mut RegMatch,
+            eflags: i32,
+        ) -> i32;
+        pub fn regfree(preg: *mut Regex);
+    }
+}
+
+pub const WNOHANG: i32 = 1;
+const O_CLOEXEC: i32 = 0o2000000;
+const F_GETFD: i32 = 1;
+const F_DUPFD_CLOEXEC: i32 = 1030;
+
+pub const SIGNALS: &[(&str, i32)] = &[
+    ("HUP", 1),
+    ("INT", 2),
+    ("QUIT", 3),
+    ("ILL", 4),
+    ("TRAP", 5),
+    ("ABRT", 6),
+    ("BUS", 7),
+    ("FPE", 8),
+    ("KILL", 9),
+    ("USR1", 10),
+    ("SEGV", 11),
+    ("USR2", 12),
+    ("PIPE", 13),
+    ("ALRM", 14),
+    ("TERM", 15),
+    ("STKFLT", 16),
+    ("CHLD", 17),
+    ("CONT", 18),
+    ("STOP", 19),
+    ("TSTP", 20),
+    ("TTIN", 21),
+    ("TTOU", 22),
+    ("URG", 23),
+    ("XCPU", 24),
+    ("XFSZ", 25),
+    ("VTALRM", 26),
+    ("PROF", 27),
+    ("WINCH", 28),
+    ("IO", 29),
+    ("PWR", 30),
+    ("SYS", 31),
+];
+
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
+pub const SIGPIPE: i32 = 13;
+pub const SIGTERM: i32 = 15;
+pub const SIGCONT: i32 = 18;
+pub const SIGTSTP: i32 = 20;
+pub const SIGTTIN: i32 = 21;
+pub const SIGTTOU: i32 = 22;
+pub const SIGWINCH: i32 = 28;
+
+// accepts `TERM`, `SIGTERM` (any case) or a number
+pub fn signal_from_str(value: &str) -> Option<i32> {
+    if let Ok(num) = value.parse::<i32>() {
+        return if num == 0 || signal_name(num).is_some() {
+            Some(num)
+        } else {
+            None
+        };
+    }
+    let upper = value.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNALS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, num)| *num)
+}
+
+pub fn signal_name(sig: i32) -> Option<&'static str> {
+    SIGNALS
+        .iter()
+        .find(|(_, n)| *n == sig)
+        .map(|(name, _)| *name)
+}
+
+pub fn signal_description(sig: i32) -> &'static str {
+    match sig {
+        1 => "Hangup",
+        2 => "Interrupt",
+        3 => "Quit",
+        4 => "Illegal instruction",
+        6 => "Aborted",
+        8 => "Floating point exception",
+        9 => "Killed",
+        11 => "Segmentation fault",
+        13 => "Broken pipe",
+        14 => "Alarm clock",
+        15 => "Terminated",
+        19 | 20 => "Stopped",
+        _ => "Unknown signal",
+    }
+}
+
+const SIG_DFL: usize = 0;
+const SIG_IGN: usize = 1;
+
+// one bit for each signal that arrived and hasn't been handled yet
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+extern "C" fn on_signal(sig: i32) {
+    PENDING.fetch_or(1 << sig, Ordering::SeqCst);
+}
+
+// puts back the default action, which the Rust runtime changes for SIGPIPE
+pub fn default_signal(sig: i32) {
+    unsafe { ffi::signal(sig, SIG_DFL) };
+}
+
+pub fn ignore_signal(sig: i32) {
+    unsafe { ffi::signal(sig, SIG_IGN) };
+}
+
+// records `sig` arriving for `take_signals` to pick up
+pub fn catch_signal(sig: i32) {
+    unsafe { ffi::signal(sig, on_signal as extern "C" fn(i32) as usize) };
+}
+
+// handles `sig` as if it had been caught, for a signal that only reached a child
+pub fn mark_caught(sig: i32) {
+    on_signal(sig);
+}
+
+// whether `sig` was caught since it was last taken, leaving the other signals pending
+pub fn take_signal(sig: i32) -> bool {
+    PENDING.fetch_and(!(1 << sig), Ordering::SeqCst) & (1 << sig) != 0
+}
+
+// the signals caught since the last call, lowest first
+pub fn take_signals() -> Vec<i32> {
+    let pending = PENDING.swap(0, Ordering::SeqCst);
+    (1..64).filter(|sig| pending & (1 << sig) != 0).collect()
+}
+
+pub fn kill(pid: i32, sig: i32) -> io::Result<()> {
+    if unsafe { ffi::kill(pid, sig) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStatus {
+    Exited(i32),
+    Signaled(i32),
+    StillAlive,
+}
+
+impl WaitStatus {
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::Exited(code) => *code,
+            Self::Signaled(sig) => 128 + sig,
+            Self::StillAlive => 0,
+        }
+    }
+}
+
+pub fn waitpid(pid: i32, options: i32) -> io::Result<WaitStatus> {
+    let mut status = 0;
+    let ret = unsafe { ffi::waitpid(pid, &mut status, options) };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    if ret == 0 {
+        return Ok(WaitStatus::StillAlive);
+    }
+    if status & 0x7f == 0 {
+        Ok(WaitStatus::Exited((status >> 8) & 0xff))
+    } else {
+        Ok(WaitStatus::Signaled(status & 0x7f))
+    }
+}
+
+fn check(ret: i32) -> io::Result<i32> {
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ret)
+}
+
+// returns 0 in the child and the child's pid in the parent
+pub fn fork() -> io::Result<i32> {
+    // anything still buffered would otherwise be written twice
+    io::stdout().flush()?;
+    check(unsafe { ffi::fork() })
+}
+
+// both ends are closed on exec, `dup2` them to hand them to a child
+pub fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    check(unsafe { ffi::pipe2(fds.as_mut_ptr(), O_CLOEXEC) })?;
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+pub fn dup2(old: i32, new: i32) -> io::Result<()> {
+    check(unsafe { ffi::dup2(old, new) })?;
+    Ok(())
+}
+
+pub fn setpgid(pid: i32, pgid: i32) -> io::Result<()> {
+    check(unsafe { ffi::setpgid(pid, pgid) })?;
+    Ok(())
+}
+
+pub fn getpid() -> i32 {
+    unsafe { ffi::getpid() }
+}
+
+pub fn getpgrp() -> i32 {
+    unsafe { ffi::getpgrp() }
+}
+
+// makes `pgid` the foreground process group of the terminal on `fd`
+pub fn tcsetpgrp(fd: i32, pgid: i32) -> io::Result<()> {
+    check(unsafe { ffi::tcsetpgrp(fd, pgid) })?;
+    Ok(())
+}
+
+// the settings of the terminal on a descriptor, to put back with `set_termios`
+#[derive(Clone, Copy)]
+pub struct Termios(ffi::Termios);
+
+// switches the terminal on `fd` to reading a key at a time without echoing it or turning
+// control keys into signals, and returns the settings it had
+pub fn raw_mode(fd: i32) -> io::Result<Termios> {
+    const ISIG: u32 = 0o1;
+    const ICANON: u32 = 0o2;
+    const ECHO: u32 = 0o10;
+    const IEXTEN: u32 = 0o100000;
+    const ICRNL: u32 = 0o400;
+    const IXON: u32 = 0o2000;
+    const VTIME: usize = 5;
+    const VMIN: usize = 6;
+    let mut termios = std::mem::MaybeUninit::<ffi::Termios>::uninit();
+    check(unsafe { ffi::tcgetattr(fd, termios.as_mut_ptr()) })?;
+    let saved = unsafe { termios.assume_init() };
+    let mut raw = saved;
+    raw.c_lflag &= !(ISIG | ICANON | ECHO | IEXTEN);
+    raw.c_iflag &= !(ICRNL | IXON);
+    raw.c_cc[VMIN] = 1;
+    raw.c_cc[VTIME] = 0;
+    set_termios(fd, &Termios(raw))?;
+    Ok(Termios(saved))
+}
+
+pub fn set_termios(fd: i32, termios: &Termios) -> io::Result<()> {
+    const TCSADRAIN: i32 = 1;
+    check(unsafe { ffi::tcsetattr(fd, TCSADRAIN, &termios.0) })?;
+    Ok(())
+}
+
+// one byte straight from `fd`, `None` at the end of the input
+pub fn read_byte(fd: i32) -> io::Result<Option<u8>> {
+    let mut byte = 0;
+    match unsafe { ffi::read(fd, &mut byte, 1) } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(None),
+        _ => Ok(Some(byte)),
+    }
+}
+
+// `time`, in seconds since the epoch, formatted by strftime(3) in the local time zone
+pub fn format_time(format: &str, time: u64) -> String {
+    let Ok(format) = CString::new(format) else {
+        return String::new();
+    };
+    let time = time as i64;
+    let mut tm = ffi::Tm {
+        fields: [0; 9],
+        tm_gmtoff: 0,
+        tm_zone: std::ptr::null(),
+    };
+    if unsafe { ffi::localtime_r(&time, &mut tm) }.is_null() {
+        return String::new();
+    }
+    let mut buf = vec![0u8; 256];
+    let len = unsafe { ffi::strftime(buf.as_mut_ptr().cast(), buf.len(), format.as_ptr(), &tm) };
+    buf.truncate(len);
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+// the columns and rows of the terminal on `fd`
+pub fn terminal_size(fd: i32) -> Option<(usize, usize)> {
+    const TIOCGWINSZ: u64 = 0x5413;
+    let mut size = ffi::Winsize::default();
+    if unsafe { ffi::ioctl(fd, TIOCGWINSZ, &mut size) } == -1 || size.ws_col == 0 {
+        return None;
+    }
+    Some((size.ws_col as usize, size.ws_row as usize))
+}
+
+// whether `fd` has input to read within `timeout` milliseconds
+pub fn readable(fd: i32, timeout: i32) -> io::Result<bool> {
+    const POLLIN: i16 = 1;
+    let mut poll = ffi::PollFd {
+        fd,
+        events: POLLIN,
+        revents: 0,
+    };
+    Ok(check(unsafe { ffi::poll(&mut poll, 1, timeout) })? > 0)
+}
+
+// waits for an exclusive lock on `fd`, which closing it releases
+pub fn lock(fd: i32) -> io::Result<()> {
+    const LOCK_EX: i32 = 2;
+    check(unsafe { ffi::flock(fd, LOCK_EX) })?;
+    Ok(())
+}
+
+pub fn close(fd: i32) -> io::Result<()> {
+    check(unsafe { ffi::close(fd) })?;
+    Ok(())
+}
+
+pub fn is_open(fd: i32) -> bool {
+    check(unsafe { ffi::fcntl(fd, F_GETFD) }).is_ok()
+}
+
+// a close-on-exec copy of `fd` numbered `min` or above
+pub fn dup_above(fd: i32, min: i32) -> io::Result<OwnedFd> {
+    let fd = check(unsafe { ffi::fcntl(fd, F_DUPFD_CLOEXEC, min) })?;
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+// whether the current user may read (4), write (2) or execute (1) `path`
+pub fn access(path: &str, mode: i32) -> bool {
+    let Ok(path) = CString::new(path) else {
+        return false;
+    };
+    unsafe { ffi::access(path.as_ptr(), mode) == 0 }
+}
+
+pub fn isatty(fd: i32) -> bool {
+    unsafe { ffi::isatty(fd) == 1 }
+}
+
+pub fn euid() -> u32 {
+    unsafe { ffi::geteuid() }
+}
+
+pub fn egid() -> u32 {
+    unsafe { ffi::getegid() }
+}
+
+// matches `text` against the POSIX extended regular expression `pattern`, giving the
+// matched text followed by what each group matched
+pub fn regex_match(pattern: &str, text: &str) -> io::Result<Option<Vec<String>>> {
+    const REG_EXTENDED: i32 = 1;
+    let invalid = || io::Error::from(io::ErrorKind::InvalidInput);
+    let pattern = CString::new(pattern).map_err(|_| invalid())?;
+    let Ok(string) = CString::new(text) else {
+        return Ok(None);
+    };
+    let mut regex = ffi::Regex::default();
+    if unsafe { ffi::regcomp(&mut regex, pattern.as_ptr(), REG_EXTENDED) } != 0 {
+        return Err(invalid());
+    }
+    let mut matches = vec![
+        ffi::RegMatch {
+            rm_so: -1,
+            rm_eo: -1
+        };
+        regex.re_nsub + 1
+    ];
+    let found = unsafe {
+        ffi::regexec(
+            &regex,
+            string.as_ptr(),
+            matches.len(),
+            matches.as_mut_ptr(),
+            0,
+        ) == 0
+    };
+    unsafe { ffi::regfree(&mut regex) };
+    if !found {
+        return Ok(None);
+    }
+    let groups =
+        matches.iter().map(
+            |m| match (usize::try_from(m.rm_so), usize::try_from(m.rm_eo)) {
+                (Ok(start), Ok(end)) => {
+                    String::from_utf8_lossy(&text.as_bytes()[start..end]).into_owned()
+                }
+                _ => String::new(),
+            },
+        );
+    Ok(Some(groups.collect()))
+}
+
+// ends a forked child without running anything the parent registered
+pub fn exit_child(status: i32) -> ! {
+    let _ = io::stdout().flush();
+    unsafe { ffi::_exit(status) }
+}
+
+// the name of the user the shell runs as, from the password database
+pub fn user_name() -> Option<String> {
+    let pw = unsafe { ffi::getpwuid(euid()) };
+    if pw.is_null() {
+        return None;
+    }
+    let name: *const c_char = unsafe { (*pw).pw_name };
+    if name.is_null() {
+        return None;
+    }
+    Some(
+        unsafe { CStr::from_ptr(name) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+pub fn host_name() -> Option<String> {
+    let mut name = [0 as c_char; 256];
+    if unsafe { ffi::gethostname(name.as_mut_ptr(), name.len() - 1) } == -1 {
+        return None;
+    }
+    Some(
+        unsafe { CStr::from_ptr(name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+// the home directory of `user` from the password database
+pub fn home_dir(user: &str) -> Option<String> {
+    let name = CString::new(user).ok()?;
+    let pw = unsafe { ffi::getpwnam(name.as_ptr()) };
+    if pw.is_null() {
+        return None;
+    }
+    let dir: *const c_char = unsafe { (*pw).pw_dir };
+    if dir.is_null() {
+        return None;
+    }
+    Some(
+        unsafe { CStr::from_ptr(dir) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
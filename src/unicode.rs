@@ -0,0 +1,227 @@
+// how wide characters are drawn in a terminal and which of them go together, as close to
+// what terminals do as a few tables of ranges get
+
+const ZWJ: char = '\u{200d}';
+
+// the columns `c` takes up, two for East Asian wide characters and emoji and none for the
+// marks drawn over the character before
+pub fn width(c: char) -> usize {
+    if is_zero_width(c) {
+        0
+    } else if is_wide(c) {
+        2
+    } else {
+        1
+    }
+}
+
+pub fn str_width(text: &str) -> usize {
+    text.chars().map(width).sum()
+}
+
+// where the terminal's cursor is, as a row and column, after writing `text` from `at` on a
+// terminal `columns` wide
+pub fn advance(
+    mut at: (usize, usize),
+    text: impl Iterator<Item = char>,
+    columns: usize,
+) -> (usize, usize) {
+    let mut joined = false;
+    for c in text {
+        let width = match c {
+            '\n' => {
+                at = (at.0 + 1, 0);
+                continue;
+            }
+            // drawn as part of the emoji before
+            _ if joined => 0,
+            c => width(c),
+        };
+        joined = c == ZWJ;
+        // a wide character that doesn't fit goes on the next row
+        if at.1 + width > columns {
+            at = (at.0 + 1, 0);
+        }
+        at.1 += width;
+        if at.1 >= columns {
+            at = (at.0 + 1, 0);
+        }
+    }
+    at
+}
+
+// where the cluster starting at `at` in `line` ends, a character with the marks, selectors
+// and joined characters that go with it, or two regional indicators making a flag
+pub fn next_boundary(line: &[char], at: usize) -> usize {
+    if at >= line.len() {
+        return line.len();
+    }
+    let mut end = at + 1;
+    if is_regional(line[at]) && line.get(end).copied().is_some_and(is_regional) {
+        end += 1;
+    }
+    while end < line.len() && (extends(line[end]) || line[end - 1] == ZWJ) {
+        end += 1;
+    }
+    end
+}
+
+// where the cluster before `at` in `line` starts
+pub fn prev_boundary(line: &[char], at: usize) -> usize {
+    // clusters are found from the start of the line, so both ways split it the same
+    let mut start = 0;
+    loop {
+        let next = next_boundary(line, start);
+        if next >= at {
+            return start;
+        }
+        start = next;
+    }
+}
+
+// whether `c` attaches to the character before it
+fn extends(c: char) -> bool {
+    is_zero_width(c) || matches!(c as u32, 0x1f3fb..=0x1f3ff)
+}
+
+fn is_regional(c: char) -> bool {
+    matches!(c as u32, 0x1f1e6..=0x1f1ff)
+}
+
+fn is_zero_width(c: char) -> bool {
+    matches!(
+        c as u32,
+        // combining diacritical marks
+        0x0300..=0x036f
+            | 0x0483..=0x0489
+            | 0x0591..=0x05bd
+            | 0x05bf
+            | 0x05c1..=0x05c2
+            | 0x05c4..=0x05c5
+            | 0x05c7
+            | 0x0610..=0x061a
+            | 0x064b..=0x065f
+            | 0x0670
+            | 0x06d6..=0x06dc
+            | 0x06df..=0x06e4
+            | 0x06e7..=0x06e8
+            | 0x06ea..=0x06ed
+            // the vowel signs and viramas of the Indic scripts that are drawn over or under
+            | 0x0900..=0x0902
+            | 0x093a
+            | 0x093c
+            | 0x0941..=0x0948
+            | 0x094d
+            | 0x0951..=0x0957
+            | 0x0962..=0x0963
+            | 0x0981
+            | 0x09bc
+            | 0x09c1..=0x09c4
+            | 0x09cd
+            | 0x09e2..=0x09e3
+            | 0x0a01..=0x0a02
+            | 0x0a3c
+            | 0x0a41..=0x0a51
+            | 0x0a70..=0x0a71
+            | 0x0a81..=0x0a82
+            | 0x0abc
+            | 0x0ac1..=0x0ac8
+            | 0x0acd
+            | 0x0b01
+            | 0x0b3c
+            | 0x0b3f
+            | 0x0b41..=0x0b44
+            | 0x0b4d
+            | 0x0bc0
+            | 0x0bcd
+            | 0x0c3e..=0x0c40
+            | 0x0c46..=0x0c56
+            | 0x0cbc
+            | 0x0ccc..=0x0ccd
+            | 0x0d41..=0x0d44
+            | 0x0d4d
+            | 0x0e31
+            | 0x0e34..=0x0e3a
+            | 0x0e47..=0x0e4e
+            | 0x1ab0..=0x1aff
+            | 0x1dc0..=0x1dff
+            // zero width space, non-joiner and joiner
+            | 0x200b..=0x200d
+            | 0x20d0..=0x20ff
+            | 0x302a..=0x302d
+            | 0x3099..=0x309a
+            // variation selectors
+            | 0xfe00..=0xfe0f
+            | 0xfe20..=0xfe2f
+            | 0xe0100..=0xe01ef
+    )
+}
+
+fn is_wide(c: char) -> bool {
+    matches!(
+        c as u32,
+        // Hangul Jamo
+        0x1100..=0x115f
+            // the symbols drawn as emoji
+            | 0x231a..=0x231b
+            | 0x2329..=0x232a
+            | 0x23e9..=0x23ec
+            | 0x23f0
+            | 0x23f3
+            | 0x25fd..=0x25fe
+            | 0x2614..=0x2615
+            | 0x2648..=0x2653
+            | 0x267f
+            | 0x2693
+            | 0x26a1
+            | 0x26aa..=0x26ab
+            | 0x26bd..=0x26be
+            | 0x26c4..=0x26c5
+            | 0x26ce
+            | 0x26d4
+            | 0x26ea
+            | 0x26f2..=0x26f3
+            | 0x26f5
+            | 0x26fa
+            | 0x26fd
+            | 0x2705
+            | 0x270a..=0x270b
+            | 0x2728
+            | 0x274c
+            | 0x274e
+            | 0x2753..=0x2755
+            | 0x2757
+            | 0x2795..=0x2797
+            | 0x27b0
+            | 0x27bf
+            | 0x2b1b..=0x2b1c
+            | 0x2b50
+            | 0x2b55
+            // CJK, kana and Hangul syllables
+            | 0x2e80..=0x303e
+            | 0x3041..=0x33ff
+            | 0x3400..=0x4dbf
+            | 0x4e00..=0x9fff
+            | 0xa000..=0xa4cf
+            | 0xa960..=0xa97f
+            | 0xac00..=0xd7a3
+            | 0xf900..=0xfaff
+            | 0xfe10..=0xfe19
+            | 0xfe30..=0xfe6f
+            // fullwidth forms
+            | 0xff00..=0xff60
+            | 0xffe0..=0xffe6
+            // emoji
+            | 0x1f004
+            | 0x1f0cf
+            | 0x1f18e
+            | 0x1f191..=0x1f19a
+            | 0x1f200..=0x1f2ff
+            | 0x1f300..=0x1f64f
+            | 0x1f680..=0x1f6ff
+            | 0x1f900..=0x1f9ff
+            | 0x1fa70..=0x1faff
+            | 0x20000..=0x2fffd
+            | 0x30000..=0x3fffd
+    )
+}
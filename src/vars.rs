@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0}: readonly variable")]
+pub struct ReadonlyError(pub String);
+
+#[derive(Debug, Clone, Default)]
+pub struct Var {
+    // `None` for names that were exported before being given a value
+    pub value: Option<String>,
+    // the elements of an indexed array from `[1]` on, `value` is `[0]`
+    pub elements: Vec<String>,
+    pub exported: bool,
+    pub readonly: bool,
+}
+
+impl Var {
+    // the attribute flags as `declare` would print them
+    pub fn flags(&self) -> &'static str {
+        match (!self.elements.is_empty(), self.readonly, self.exported) {
+            (true, true, true) => "-arx",
+            (true, true, false) => "-ar",
+            (true, false, true) => "-ax",
+            (true, false, false) => "-a",
+            (false, true, true) => "-rx",
+            (false, true, false) => "-r",
+            (false, false, true) => "-x",
+            (false, false, false) => "--",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Vars {
+    map: BTreeMap<String, Var>,
+    // one entry per running function, with what each of its `local` names hid
+    scopes: Vec<BTreeMap<String, Option<Var>>>,
+}
+
+impl Vars {
+    pub fn from_env() -> Self {
+        let mut vars = Self::default();
+        for (name, value) in std::env::vars_os() {
+            vars.map.insert(
+                name.to_string_lossy().into_owned(),
+                Var {
+                    value: Some(value.to_string_lossy().into_owned()),
+                    exported: true,
+                    ..Default::default()
+                },
+            );
+        }
+        vars
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.map.get(name)?.value.as_deref()
+    }
+
+    pub fn var(&self, name: &str) -> Option<&Var> {
+        self.map.get(name)
+    }
+
+    pub fn is_readonly(&self, name: &str) -> bool {
+        self.map.get(name).is_some_and(|var| var.readonly)
+    }
+
+    pub fn set(&mut self, name: &str, value: String) -> Result<(), ReadonlyError> {
+        let var = self.map.entry(name.to_owned()).or_default();
+        if var.readonly {
+            return Err(ReadonlyError(name.to_owned()));
+        }
+        var.value = Some(value);
+        Ok(())
+    }
+
+    pub fn set_array(&mut self, name: &str, values: Vec<String>) -> Result<(), ReadonlyError> {
+        let var = self.map.entry(name.to_owned()).or_default();
+        if var.readonly {
+            return Err(ReadonlyError(name.to_owned()));
+        }
+        let mut values = values.into_iter();
+        var.value = values.next();
+        var.elements = values.collect();
+        Ok(())
+    }
+
+    // every element of an array, a plain variable is an array of one
+    pub fn elements(&self, name: &str) -> Vec<&str> {
+        match self.map.get(name) {
+            Some(var) => (var.value.iter().chain(&var.elements))
+                .map(String::as_str)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    // a negative `index` counts back from the end
+    pub fn element(&self, name: &str, index: i64) -> Option<&str> {
+        let elements = self.elements(name);
+        let index = if index < 0 {
+            elements.len().checked_sub(index.unsigned_abs() as usize)?
+        } else {
+            index as usize
+        };
+        elements.get(index).copied()
+    }
+
+    pub fn export(&mut self, name: &str, value: Option<String>) -> Result<(), ReadonlyError> {
+        let var = self.map.entry(name.to_owned()).or_default();
+        if var.readonly && value.is_some() {
+            return Err(ReadonlyError(name.to_owned()));
+        }
+        var.exported = true;
+        if value.is_some() {
+            var.value = value;
+        }
+        Ok(())
+    }
+
+    pub fn readonly(&mut self, name: &str, value: Option<String>) -> Result<(), ReadonlyError> {
+        let var = self.map.entry(name.to_owned()).or_default();
+        if var.readonly && value.is_some() {
+            return Err(ReadonlyError(name.to_owned()));
+        }
+        if value.is_some() {
+            var.value = value;
+        }
+        var.readonly = true;
+        Ok(())
+    }
+
+    pub fn unset(&mut self, name: &str) -> Result<(), ReadonlyError> {
+        if self.is_readonly(name) {
+            return Err(ReadonlyError(name.to_owned()));
+        }
+        self.map.remove(name);
+        Ok(())
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(BTreeMap::new());
+    }
+
+    // puts back every variable the innermost scope made local
+    pub fn pop_scope(&mut self) {
+        for (name, var) in self.scopes.pop().unwrap_or_default() {
+            match var {
+                Some(var) => self.map.insert(name, var),
+                None => self.map.remove(&name),
+            };
+        }
+    }
+
+    pub fn has_scope(&self) -> bool {
+        !self.scopes.is_empty()
+    }
+
+    // the names made local in the innermost scope
+    pub fn locals(&self) -> impl Iterator<Item = &str> {
+        self.scopes
+            .last()
+            .into_iter()
+            .flat_map(|scope| scope.keys().map(String::as_str))
+    }
+
+    // `name` is unset in the innermost scope until it gets a value, and its old value
+    // comes back when that scope is popped
+    pub fn local(&mut self, name: &str, value: Option<String>) -> Result<(), ReadonlyError> {
+        if self.is_readonly(name) {
+            return Err(ReadonlyError(name.to_owned()));
+        }
+        let Some(scope) = self.scopes.last_mut() else {
+            return self.set(name, value.unwrap_or_default());
+        };
+        if !scope.contains_key(name) {
+            scope.insert(name.to_owned(), self.map.remove(name));
+        }
+        if let Some(value) = value {
+            self.set(name, value)?;
+        }
+        Ok(())
+    }
+
+    pub fn unexport(&mut self, name: &str) {
+        if let Some(var) = self.map.get_mut(name) {
+            var.exported = false;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Var)> {
+        self.map.iter().map(|(name, var)| (name.as_str(), var))
+    }
+
+    // the environment handed to child processes
+    pub fn exported(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.map.iter().filter_map(|(name, var)| match &var.value {
+            Some(value) if var.exported => Some((name.as_str(), value.as_str())),
+            _ => None,
+        })
+    }
+}
+
+pub fn is_name(value: &str) -> bool {
+    let mut chars = value.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// `a b` -> `'a b'`, values that need no quoting are left as they are, the form `set` prints
+// values in
+pub fn single_quote(value: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-./:,=+@%^".contains(c);
+    if !value.is_empty() && value.chars().all(plain) {
+        return value.to_owned();
+    }
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+// `a"b` -> `"a\"b"`, the form `export -p` prints values in
+pub fn double_quote(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+    out
+}